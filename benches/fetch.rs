@@ -0,0 +1,62 @@
+//! Times tight loops of `Depot::fetch`: over plaintext values, whose lookups
+//! reuse a cached prepared statement, and over encrypted values with the
+//! derived-key cache off and on. Run with `cargo bench`.
+
+use std::time::Instant;
+
+const KEYS: usize = 100;
+const ROUNDS: usize = 100;
+const ENCRYPTED_ROUNDS: usize = 5;
+const PASSWORD: &str = "password";
+
+fn main() {
+    let path = std::env::temp_dir().join(format!("depot-bench-{}.db", std::process::id()));
+    let keys: Vec<String> = (0..KEYS).map(|i| format!("bench{}", i)).collect();
+    let secrets: Vec<String> = (0..KEYS).map(|i| format!("secret{}", i)).collect();
+    let storage = depot::Depot::open_path(&path).unwrap();
+    for (key, secret) in keys.iter().zip(&secrets) {
+        storage.stow(key, "testing123", None).unwrap();
+        storage.stow(secret, "testing123", Some(PASSWORD)).unwrap();
+    }
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for key in &keys {
+            storage.fetch(key, None).unwrap();
+        }
+    }
+    report("fetch (plaintext)", start, ROUNDS);
+    drop(storage);
+
+    for cache_keys in [false, true] {
+        let opts = depot::Options {
+            cache_keys,
+            ..Default::default()
+        };
+        let storage = depot::Depot::with_options(path.to_str().unwrap(), opts).unwrap();
+        let start = Instant::now();
+        for _ in 0..ENCRYPTED_ROUNDS {
+            for secret in &secrets {
+                storage.fetch(secret, Some(PASSWORD)).unwrap();
+            }
+        }
+        let name = match cache_keys {
+            false => "fetch (encrypted)",
+            true => "fetch (encrypted, cache_keys)",
+        };
+        report(name, start, ENCRYPTED_ROUNDS);
+    }
+
+    for ext in ["", "-wal", "-shm"] {
+        let mut file = path.clone().into_os_string();
+        file.push(ext);
+        let _ = std::fs::remove_file(file);
+    }
+}
+
+/// Prints the average time per fetch of the given number of rounds over
+/// every key begun at the given instant.
+fn report(name: &str, start: Instant, rounds: usize) {
+    let per_fetch = start.elapsed() / (KEYS * rounds) as u32;
+    println!("{:<40} {:>10?} per fetch", name, per_fetch);
+}
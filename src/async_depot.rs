@@ -0,0 +1,76 @@
+//! An async facade over a depot for applications running on tokio.
+//! SQLite is synchronous, so this is no faster than the depot itself; it only
+//! keeps each blocking call off the executor's worker threads by running it on
+//! tokio's blocking thread pool.
+
+use crate::{Depot, Error, Result};
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+/// A depot whose operations are async, each running the corresponding
+/// `Depot` method on tokio's blocking thread pool with `spawn_blocking`.
+/// Cloning it is cheap and every clone shares the same depot. It must be
+/// used from within a tokio runtime.
+#[derive(Clone)]
+pub struct AsyncDepot {
+    depot: Arc<Depot>,
+}
+
+impl AsyncDepot {
+    /// Returns an async facade over the given depot.
+    pub fn new(depot: Depot) -> AsyncDepot {
+        AsyncDepot {
+            depot: Arc::new(depot),
+        }
+    }
+
+    /// Returns the underlying depot, for the operations without an async
+    /// counterpart. Its methods block, so call them from a blocking context.
+    pub fn depot(&self) -> &Depot {
+        &self.depot
+    }
+
+    /// Stores the specified key and value as with `Depot::stow`.
+    pub async fn stow(&self, key: &str, val: &str, password: Option<&str>) -> Result<()> {
+        let key = String::from(key);
+        let val = Zeroizing::new(String::from(val));
+        let password = password.map(|p| Zeroizing::new(String::from(p)));
+        self.run(move |depot| depot.stow(&key, &val, password.as_deref().map(|p| p.as_str())))
+            .await
+    }
+
+    /// Returns the value associated with the specified key as with
+    /// `Depot::fetch`.
+    pub async fn fetch(&self, key: &str, password: Option<&str>) -> Result<String> {
+        let key = String::from(key);
+        let password = password.map(|p| Zeroizing::new(String::from(p)));
+        self.run(move |depot| depot.fetch(&key, password.as_deref().map(|p| p.as_str())))
+            .await
+    }
+
+    /// Removes the specified key as with `Depot::drop`.
+    pub async fn drop(&self, key: &str) -> Result<bool> {
+        let key = String::from(key);
+        self.run(move |depot| depot.drop(&key)).await
+    }
+
+    /// Returns every key as with `Depot::keys`.
+    pub async fn keys(&self) -> Result<Vec<String>> {
+        self.run(|depot| depot.keys()).await
+    }
+
+    /// Runs the given operation on the depot on the blocking thread pool and
+    /// returns its result, resuming the panic if the operation panicked.
+    async fn run<T, F>(&self, op: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Depot) -> Result<T> + Send + 'static,
+    {
+        let depot = Arc::clone(&self.depot);
+        match tokio::task::spawn_blocking(move || op(&depot)).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(Error::from(e.to_string())),
+        }
+    }
+}
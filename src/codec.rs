@@ -0,0 +1,61 @@
+//! The text encodings in which a depot stores its encrypted and compressed
+//! values: base64 in either alphabet, or hex.
+
+use base64::prelude::{BASE64_STANDARD, BASE64_URL_SAFE};
+use base64::Engine;
+
+/// The encoding of a depot's encrypted and compressed values, fixed when the
+/// depot is created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Codec {
+    Base64,
+    Base64UrlSafe,
+    Hex,
+}
+
+impl Codec {
+    /// Returns the given bytes encoded as text.
+    pub(crate) fn encode(self, data: impl AsRef<[u8]>) -> String {
+        match self {
+            Codec::Base64 => BASE64_STANDARD.encode(data),
+            Codec::Base64UrlSafe => BASE64_URL_SAFE.encode(data),
+            Codec::Hex => data.as_ref().iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+
+    /// Returns the bytes encoded in the given text or, if it is not validly
+    /// encoded, why not, e.g. "not valid hex (odd number of digits)".
+    pub(crate) fn decode(self, text: &str) -> std::result::Result<Vec<u8>, String> {
+        match self {
+            Codec::Base64 => BASE64_STANDARD.decode(text).map_err(not_base64),
+            Codec::Base64UrlSafe => BASE64_URL_SAFE.decode(text).map_err(not_base64),
+            Codec::Hex => {
+                if !text.len().is_multiple_of(2) {
+                    return Err(String::from("not valid hex (odd number of digits)"));
+                }
+                text.as_bytes()
+                    .chunks(2)
+                    .enumerate()
+                    .map(|(i, pair)| match (hex_digit(pair[0]), hex_digit(pair[1])) {
+                        (Some(high), Some(low)) => Ok(high << 4 | low),
+                        _ => Err(format!(
+                            "not valid hex (invalid digits at offset {})",
+                            i * 2
+                        )),
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Returns why text is not valid base64, given the error decoding it.
+fn not_base64(e: base64::DecodeError) -> String {
+    format!("not valid base64 ({})", e)
+}
+
+/// Returns the value of the given hex digit, in either case, or None if it
+/// is not one.
+fn hex_digit(c: u8) -> Option<u8> {
+    char::from(c).to_digit(16).map(|d| d as u8)
+}
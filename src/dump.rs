@@ -0,0 +1,242 @@
+//! The portable format in which `Depot::export_encrypted` writes a depot's
+//! salt, sentinel, and entries exactly as they are stored, without decrypting
+//! anything, for `Depot::import_encrypted` to read back.
+//!
+//! A dump is the magic bytes `DEPOTDMP`, a format version byte, and then the
+//! fields of the dump in order. Integers are little-endian, byte strings and
+//! strings are preceded by their length as a u32, and optional fields by a
+//! byte that is 1 if the field is present and 0 if not.
+
+use crate::codec::Codec;
+use crate::{Error, Kdf, KdfHash, Result};
+
+const MAGIC: &[u8] = b"DEPOTDMP";
+
+/// The version of the format written. Version 1, which is also read, is the
+/// same but without tags.
+const VERSION: u8 = 2;
+
+/// A depot's salt, sentinel, and entries as they are stored.
+pub(crate) struct Dump {
+    pub(crate) salt: Vec<u8>,
+    pub(crate) codec: Codec,
+    pub(crate) sentinel: Option<(String, Vec<u8>, Kdf)>,
+    /// Whether the entries' tags are recorded, as they are not in version 1.
+    pub(crate) tagged: bool,
+    pub(crate) entries: Vec<DumpEntry>,
+}
+
+/// One entry as it is stored, its value still encrypted if it is encrypted.
+pub(crate) struct DumpEntry {
+    pub(crate) key: String,
+    pub(crate) modified: Option<i64>,
+    pub(crate) val: String,
+    pub(crate) nonce: Option<Vec<u8>>,
+    pub(crate) custom: bool,
+    pub(crate) compressed: bool,
+    pub(crate) bound: bool,
+    pub(crate) kdf: Kdf,
+    pub(crate) expires: Option<i64>,
+    pub(crate) hint: Option<String>,
+    pub(crate) tags: Option<String>,
+}
+
+impl Dump {
+    /// Returns the dump in the portable format.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::from(MAGIC);
+        out.push(VERSION);
+        put_bytes(&mut out, &self.salt);
+        out.push(match self.codec {
+            Codec::Base64 => 0,
+            Codec::Base64UrlSafe => 1,
+            Codec::Hex => 2,
+        });
+        put_opt(
+            &mut out,
+            self.sentinel.as_ref(),
+            |out, (val, nonce, kdf)| {
+                put_bytes(out, val.as_bytes());
+                put_bytes(out, nonce);
+                put_kdf(out, *kdf);
+            },
+        );
+
+        out.extend((self.entries.len() as u32).to_le_bytes());
+        for e in self.entries.iter() {
+            put_bytes(&mut out, e.key.as_bytes());
+            put_opt(&mut out, e.modified, |out, t| out.extend(t.to_le_bytes()));
+            put_bytes(&mut out, e.val.as_bytes());
+            put_opt(&mut out, e.nonce.as_ref(), |out, n| put_bytes(out, n));
+            out.push(e.custom as u8);
+            out.push(e.compressed as u8);
+            out.push(e.bound as u8);
+            put_kdf(&mut out, e.kdf);
+            put_opt(&mut out, e.expires, |out, t| out.extend(t.to_le_bytes()));
+            put_opt(&mut out, e.hint.as_ref(), |out, h| {
+                put_bytes(out, h.as_bytes())
+            });
+            put_opt(&mut out, e.tags.as_ref(), |out, t| {
+                put_bytes(out, t.as_bytes())
+            });
+        }
+        out
+    }
+
+    /// Returns the dump in the given bytes or an error if they are not a dump
+    /// in a supported version of the portable format.
+    pub(crate) fn decode(data: &[u8]) -> Result<Dump> {
+        let mut r = Reader { data };
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(Error::from("not an encrypted depot export"));
+        }
+        let version = r.byte()?;
+        if version != 1 && version != VERSION {
+            return Err(Error::from(format!(
+                "unsupported encrypted depot export version: {}",
+                version
+            )));
+        }
+
+        let salt = r.bytes()?;
+        let codec = match r.byte()? {
+            0 => Codec::Base64,
+            1 => Codec::Base64UrlSafe,
+            2 => Codec::Hex,
+            _ => return Err(malformed()),
+        };
+        let sentinel = match r.flag()? {
+            true => Some((r.string()?, r.bytes()?, r.kdf()?)),
+            false => None,
+        };
+
+        let tagged = version >= 2;
+        let count = r.u32()?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            entries.push(DumpEntry {
+                key: r.string()?,
+                modified: r.opt(|r| r.i64())?,
+                val: r.string()?,
+                nonce: r.opt(|r| r.bytes())?,
+                custom: r.flag()?,
+                compressed: r.flag()?,
+                bound: r.flag()?,
+                kdf: r.kdf()?,
+                expires: r.opt(|r| r.i64())?,
+                hint: r.opt(|r| r.string())?,
+                tags: match tagged {
+                    true => r.opt(|r| r.string())?,
+                    false => None,
+                },
+            });
+        }
+        if !r.data.is_empty() {
+            return Err(malformed());
+        }
+
+        Ok(Dump {
+            salt,
+            codec,
+            sentinel,
+            tagged,
+            entries,
+        })
+    }
+}
+
+/// Appends the given bytes preceded by their length.
+fn put_bytes(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend((data.len() as u32).to_le_bytes());
+    out.extend(data);
+}
+
+/// Appends whether the given field is present and, if it is, the field as
+/// written by the given function.
+fn put_opt<T>(out: &mut Vec<u8>, field: Option<T>, put: impl FnOnce(&mut Vec<u8>, T)) {
+    match field {
+        Some(f) => {
+            out.push(1);
+            put(out, f);
+        }
+        None => out.push(0),
+    }
+}
+
+/// Appends the hash and iteration count of the given KDF.
+fn put_kdf(out: &mut Vec<u8>, kdf: Kdf) {
+    out.push(match kdf.hash {
+        KdfHash::Sha1 => 0,
+        KdfHash::Sha256 => 1,
+    });
+    out.extend(kdf.iterations.to_le_bytes());
+}
+
+/// Returns the error for a dump that ends early or has a malformed field.
+fn malformed() -> Error {
+    Error::from("malformed encrypted depot export")
+}
+
+/// Reads the fields of a dump from the front of the remaining bytes.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.data.len() {
+            return Err(malformed());
+        }
+        let (field, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(field)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn flag(&mut self) -> Result<bool> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(malformed()),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        Ok(Vec::from(self.take(len)?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        String::from_utf8(self.bytes()?).map_err(|_| malformed())
+    }
+
+    fn kdf(&mut self) -> Result<Kdf> {
+        let hash = match self.byte()? {
+            0 => KdfHash::Sha1,
+            1 => KdfHash::Sha256,
+            _ => return Err(malformed()),
+        };
+        Ok(Kdf {
+            hash,
+            iterations: self.u32()?,
+        })
+    }
+
+    fn opt<T>(&mut self, get: impl FnOnce(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        match self.flag()? {
+            true => Ok(Some(get(self)?)),
+            false => Ok(None),
+        }
+    }
+}
@@ -1,47 +1,141 @@
 pub enum Error {
+    AlreadyExists(String),
     AnyErr(String),
-    B64Err(base64::DecodeError),
     BadPassword,
+    DecryptFailed(String),
+    Expired(String),
+    InvalidKey(String),
     IoErr(std::io::Error),
+    #[cfg(feature = "serde")]
+    JsonErr(serde_json::Error),
+    Malformed(String, String),
     NeedPassword,
-    NotFound,
+    NoDepot(String),
+    NotFound(String),
+    #[cfg(feature = "pool")]
+    PoolErr(r2d2::Error),
     SqlErr(rusqlite::Error),
+    TooLarge(String),
     Utf8Err(std::string::FromUtf8Error),
 }
 
+/// The kind of an error, for programs to act on without matching every
+/// variant of `Error`. New kinds may be added, but what kind an error is
+/// will not change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// The key, or the depot itself, does not exist or has expired.
+    NotFound,
+    /// A password is needed to decrypt the value but none was given.
+    NeedPassword,
+    /// The password given does not decrypt the value.
+    BadPassword,
+    /// Reading or writing a file failed.
+    Io,
+    /// The database failed.
+    Sql,
+    /// A stored or exported value is not validly encoded or not valid UTF-8,
+    /// or a value is not the JSON expected.
+    Encoding,
+    /// Anything else, such as an invalid key or a damaged value.
+    Other,
+}
+
+impl Error {
+    /// Returns the kind of the error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::NotFound(_) | Error::Expired(_) | Error::NoDepot(_) => ErrorCode::NotFound,
+            Error::NeedPassword => ErrorCode::NeedPassword,
+            Error::BadPassword => ErrorCode::BadPassword,
+            Error::IoErr(_) => ErrorCode::Io,
+            Error::SqlErr(_) => ErrorCode::Sql,
+            #[cfg(feature = "pool")]
+            Error::PoolErr(_) => ErrorCode::Sql,
+            Error::Malformed(..) | Error::Utf8Err(_) => ErrorCode::Encoding,
+            #[cfg(feature = "serde")]
+            Error::JsonErr(_) => ErrorCode::Encoding,
+            Error::AlreadyExists(_)
+            | Error::AnyErr(_)
+            | Error::DecryptFailed(_)
+            | Error::InvalidKey(_)
+            | Error::TooLarge(_) => ErrorCode::Other,
+        }
+    }
+}
+
 impl std::fmt::Debug for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Error::AlreadyExists(k) => write!(f, "key already exists: {}", k),
             Error::AnyErr(s) => write!(f, "{}", s),
-            Error::B64Err(e) => e.fmt(f),
             Error::BadPassword => write!(f, "bad password"),
+            Error::DecryptFailed(k) => write!(
+                f,
+                "cannot decrypt {}: the value is corrupt or has been tampered with",
+                k
+            ),
+            Error::Expired(k) => write!(f, "key has expired: {}", k),
+            Error::InvalidKey(s) => write!(f, "invalid key: {}", s),
             Error::IoErr(e) => e.fmt(f),
+            #[cfg(feature = "serde")]
+            Error::JsonErr(e) => e.fmt(f),
+            Error::Malformed(k, e) => write!(
+                f,
+                "the stored value of {} is {}, so the database may be damaged",
+                k, e
+            ),
             Error::NeedPassword => write!(f, "password required but not supplied"),
-            Error::NotFound => write!(f, "key not found"),
+            Error::NoDepot(p) => write!(f, "no depot at {}", p),
+            Error::NotFound(k) => write!(f, "key not found: {}", k),
+            #[cfg(feature = "pool")]
+            Error::PoolErr(e) => e.fmt(f),
             Error::SqlErr(e) => e.fmt(f),
+            Error::TooLarge(s) => write!(f, "value too large: {}", s),
             Error::Utf8Err(e) => e.fmt(f),
         }
     }
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IoErr(e) => write!(f, "{}", e),
+            #[cfg(feature = "serde")]
+            Error::JsonErr(e) => write!(f, "{}", e),
+            #[cfg(feature = "pool")]
+            Error::PoolErr(e) => write!(f, "{}", e),
+            Error::SqlErr(e) => write!(f, "{}", e),
+            Error::Utf8Err(e) => write!(f, "{}", e),
+            e => write!(f, "{:?}", e),
+        }
+    }
+}
+
 impl From<std::string::FromUtf8Error> for Error {
     fn from(e: std::string::FromUtf8Error) -> Error {
         Error::Utf8Err(e)
     }
 }
 
-impl From<base64::DecodeError> for Error {
-    fn from(e: base64::DecodeError) -> Error {
-        Error::B64Err(e)
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::SqlErr(e)
+    }
+}
+
+#[cfg(feature = "pool")]
+impl From<r2d2::Error> for Error {
+    fn from(e: r2d2::Error) -> Error {
+        Error::PoolErr(e)
     }
 }
 
-impl From<rusqlite::Error> for Error {
-    fn from(e: rusqlite::Error) -> Error {
-        match e {
-            rusqlite::Error::QueryReturnedNoRows => Error::NotFound,
-            other => Error::SqlErr(other),
-        }
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::JsonErr(e)
     }
 }
 
@@ -53,6 +147,8 @@ impl From<std::io::Error> for Error {
 
 impl From<aes_gcm::Error> for Error {
     fn from(_: aes_gcm::Error) -> Error {
+        // AES-GCM cannot tell a wrong key from damaged data, so a failure is
+        // presumed to be a wrong password unless the depot can tell otherwise.
         Error::BadPassword
     }
 }
@@ -2,154 +2,3478 @@
 //! Use it as a repository for reminders, trivia, or even
 //! sensitive information such as passwords.
 
-use aes_gcm::{aead::Aead, AeadCore, Aes256Gcm, KeyInit};
-use base64::prelude::BASE64_STANDARD as b64;
-use base64::Engine;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit};
+use codec::Codec;
+use hmac::{Hmac, Mac};
 use pbkdf2::pbkdf2_hmac;
-use rand::RngCore;
+use rand::{CryptoRng, RngCore};
+use rusqlite::OptionalExtension;
 use sha1::Sha1;
+use sha2::Sha256;
+use sql::{Prefixed, Sql, Tx};
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use zeroize::{Zeroize, Zeroizing};
 
 pub mod error;
-pub use error::Error;
+pub use error::{Error, ErrorCode};
+
+#[cfg(feature = "tokio")]
+mod async_depot;
+mod codec;
+mod dump;
+mod migrate;
+mod scoped;
+mod sql;
+mod strength;
+#[cfg(feature = "tokio")]
+pub use async_depot::AsyncDepot;
+pub use scoped::ScopedDepot;
+pub use strength::{password_strength, Strength};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub struct Depot {
-    db: rusqlite::Connection,
-    salt: [u8; 32],
-}
+/// ASCII digits.
+pub const DIGITS: &str = "0123456789";
+
+/// Uppercase and lowercase ASCII letters and digits.
+pub const ALPHANUMERIC: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Uppercase and lowercase ASCII letters, digits, and punctuation,
+/// i.e. every printable ASCII character except the space.
+pub const PRINTABLE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789\
+    !\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+/// The number of keys `Depot::iter` reads from the database at a time.
+const ITER_BATCH: usize = 100;
+
+/// The known plaintext encrypted to verify the master password.
+const SENTINEL: &str = "depot";
+
+/// The name of the meta entry holding the password-verification sentinel.
+const META_SENTINEL: &str = "sentinel";
+
+/// The name of the meta entry recording the depot's base64 alphabet,
+/// which is the standard alphabet if there is no such entry.
+const META_BASE64: &str = "base64";
+
+/// The value of the base64 meta entry for the URL-safe alphabet.
+const BASE64_URL: &str = "url_safe";
+
+/// The name of the meta entry recording that a depot stores its encrypted
+/// and compressed values in an encoding other than base64. A depot without
+/// one, as every depot made before hex was supported, stores them in base64.
+const META_ENCODING: &str = "encoding";
+
+/// The value of the encoding meta entry for hex.
+const ENCODING_HEX: &str = "hex";
+
+/// The length in bytes of the salt generated for a depot by default, and of
+/// every salt generated before the length was configurable.
+pub const SALT_LEN: usize = 32;
+
+/// The shortest salt, in bytes, that a depot can be configured to generate.
+pub const MIN_SALT_LEN: usize = 16;
+
+/// The number of PBKDF2 iterations used to derive encryption keys by default,
+/// and by every value encrypted before the count was configurable.
+pub const KDF_ITERATIONS: u32 = 4096;
+
+/// The hash function with which PBKDF2 derives encryption keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KdfHash {
+    /// HMAC-SHA1, used by every value encrypted before SHA-256 was supported.
+    Sha1,
+    /// HMAC-SHA256, the default.
+    Sha256,
+}
+
+impl rusqlite::types::ToSql for KdfHash {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(rusqlite::types::ToSqlOutput::from(match self {
+            KdfHash::Sha1 => 0,
+            KdfHash::Sha256 => 1,
+        }))
+    }
+}
+
+impl rusqlite::types::FromSql for KdfHash {
+    fn column_result(val: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        match val.as_i64()? {
+            0 => Ok(KdfHash::Sha1),
+            1 => Ok(KdfHash::Sha256),
+            n => Err(rusqlite::types::FromSqlError::OutOfRange(n)),
+        }
+    }
+}
+
+/// How an encryption key is derived from a password, as recorded along with
+/// each encrypted value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Kdf {
+    hash: KdfHash,
+    iterations: u32,
+}
+
+/// The text encoding in which a depot stores encrypted and compressed values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Base64, in the alphabet chosen by the `url_safe_base64` option, the
+    /// default.
+    Base64,
+    /// Lowercase hex, twice the size of the bytes encoded but easier to read.
+    Hex,
+}
+
+/// Options controlling how a depot is opened and stores its values.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Compress values before storing them, whenever doing so makes them
+    /// smaller. Compressed values can be read back regardless of this option.
+    pub compress: bool,
+
+    /// How long to wait for another process to release the database
+    /// before failing with a "database is locked" error.
+    pub busy_timeout: Duration,
+
+    /// The number of times a write that fails because the database is busy
+    /// or locked, even after the busy timeout, is retried before the error is
+    /// returned.
+    pub busy_retries: u32,
+
+    /// How long to wait before the first retry of a write that failed because
+    /// the database is busy or locked, doubling before each further retry.
+    pub busy_backoff: Duration,
+
+    /// The maximum length of a key in bytes.
+    pub max_key_len: usize,
+
+    /// The maximum length of a value in bytes, before any compression or
+    /// encryption, so that a misdirected pipe cannot bloat the database.
+    /// Storing a longer value is a `TooLarge` error. Defaults to 4 MiB.
+    pub max_value_len: usize,
+
+    /// The number of PBKDF2 iterations used to derive the key for each value
+    /// encrypted from now on. Each value records the count it was encrypted
+    /// with, so values encrypted with a different count can still be read.
+    pub kdf_iterations: u32,
+
+    /// The hash function PBKDF2 uses to derive the key for each value
+    /// encrypted from now on. As with the iteration count, each value
+    /// records the hash it was encrypted with.
+    pub kdf_hash: KdfHash,
+
+    /// The length in bytes of the salt generated for a depot being created
+    /// or by `rotate_salt`, `SALT_LEN` by default and at least
+    /// `MIN_SALT_LEN`. An existing depot keeps the salt it has, of whatever
+    /// length, until it is rotated.
+    pub salt_len: usize,
+
+    /// Re-encrypt a value encrypted with the legacy SHA-1 KDF using the
+    /// current KDF whenever it is fetched, gradually upgrading the depot.
+    pub upgrade_on_fetch: bool,
+
+    /// Encode encrypted and compressed values in base64 with the URL-safe
+    /// alphabet (`-` and `_`) rather than the standard alphabet (`+` and `/`).
+    /// This only applies to a depot being created; the depot records its
+    /// alphabet, which is used from then on regardless of this option.
+    pub url_safe_base64: bool,
+
+    /// The encoding in which encrypted and compressed values are stored.
+    /// As with `url_safe_base64`, this only applies to a depot being created;
+    /// the depot records its encoding, which is used from then on.
+    pub encoding: Encoding,
+
+    /// Delete every expired value, in every store, whenever the depot is
+    /// opened. Since this writes to the database on opening, it is off by
+    /// default; `prune_expired` deletes expired values on demand.
+    pub prune_on_open: bool,
+
+    /// Record when each value was last fetched and how many times it has
+    /// been, as reported by `metadata`.
+    pub track_access: bool,
+
+    /// The number of previous versions of each value to keep, as listed by
+    /// `history`, when a value is replaced. None are kept by default.
+    pub history_limit: usize,
+
+    /// Move dropped keys to the trash, from which `undelete` can bring them
+    /// back, rather than deleting them outright. Keys in the trash are hidden
+    /// until `empty_trash` deletes them.
+    pub soft_delete: bool,
+
+    /// A prefix for the names of the depot's tables, e.g. `myapp_` to name
+    /// them `myapp_storage`, `myapp_salt`, and so on, so that a depot can
+    /// share a database with an application's own tables. The prefix must be
+    /// an ASCII letter or underscore followed by ASCII letters, digits, and
+    /// underscores. Depots with different prefixes in the same database are
+    /// independent of one another. By default the tables have no prefix.
+    pub table_prefix: String,
+
+    /// Remember each encryption key derived from a password for the life of
+    /// the depot, so that operations over many encrypted values, such as
+    /// `change_password`, `check`, and exporting, run PBKDF2 once rather than
+    /// once per value. Since the derived keys then stay in memory until the
+    /// depot is dropped or `clear_key_cache` is called, this is off by default.
+    pub cache_keys: bool,
+
+    /// Have destructive and bulk operations report what they would do without
+    /// changing anything: `drop`, `drop_matching`, `clear`, `prune_expired`,
+    /// `change_password`, `import_csv`, and `import_encrypted` check what they
+    /// can and return what they would have returned, but write nothing.
+    /// Other writes, such as `stow` and `touch`, are made as usual.
+    pub dry_run: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            compress: false,
+            busy_timeout: Duration::from_secs(5),
+            busy_retries: 3,
+            busy_backoff: Duration::from_millis(50),
+            max_key_len: 1024,
+            max_value_len: 4 * 1024 * 1024,
+            kdf_iterations: KDF_ITERATIONS,
+            kdf_hash: KdfHash::Sha256,
+            salt_len: SALT_LEN,
+            upgrade_on_fetch: false,
+            url_safe_base64: false,
+            encoding: Encoding::Base64,
+            prune_on_open: false,
+            track_access: false,
+            history_limit: 0,
+            soft_delete: false,
+            table_prefix: String::new(),
+            cache_keys: false,
+            dry_run: false,
+        }
+    }
+}
+
+impl Options {
+    /// Returns an error if any option is out of range.
+    fn validate(&self) -> Result<()> {
+        if self.kdf_iterations == 0 {
+            return Err(Error::from("KDF iterations must be greater than zero"));
+        }
+        if self.salt_len < MIN_SALT_LEN {
+            return Err(Error::from(format!(
+                "the salt must be at least {} bytes",
+                MIN_SALT_LEN
+            )));
+        }
+        if !sql::valid_prefix(&self.table_prefix) {
+            return Err(Error::from(format!(
+                "invalid table prefix: {}",
+                self.table_prefix
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A summary of the contents of a depot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of keys in the depot.
+    pub total: usize,
+
+    /// The number of encrypted values.
+    pub encrypted: usize,
+
+    /// The number of unencrypted values.
+    pub plaintext: usize,
+
+    /// The earliest time any value was last modified, as a Unix timestamp,
+    /// or None if the depot is empty.
+    pub earliest: Option<i64>,
+
+    /// The latest time any value was modified, as a Unix timestamp,
+    /// or None if the depot is empty.
+    pub latest: Option<i64>,
+
+    /// The size of the database file in bytes, not counting any
+    /// write-ahead log not yet checkpointed into it.
+    pub file_size: u64,
+}
+
+/// The characters from which `generate` draws a value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Charset {
+    /// `DIGITS`, e.g. for a PIN.
+    Digits,
+    /// `ALPHANUMERIC`, for systems that allow no punctuation.
+    Alphanumeric,
+    /// `PRINTABLE`, the default.
+    Printable,
+    /// The given characters, e.g. to meet a password policy that allows
+    /// only certain punctuation. Repeated characters count once.
+    Custom(String),
+}
+
+impl Charset {
+    /// Returns the characters in the set, without repeats, in order.
+    fn chars(&self) -> Vec<char> {
+        match self {
+            Charset::Digits => DIGITS.chars().collect(),
+            Charset::Alphanumeric => ALPHANUMERIC.chars().collect(),
+            Charset::Printable => PRINTABLE.chars().collect(),
+            Charset::Custom(s) => {
+                let mut chars = Vec::new();
+                for c in s.chars() {
+                    if !chars.contains(&c) {
+                        chars.push(c);
+                    }
+                }
+                chars
+            }
+        }
+    }
+}
+
+/// How `generate` makes a value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenOptions {
+    /// The number of characters in the value. Defaults to 20.
+    pub length: usize,
+
+    /// The characters the value is drawn from. Defaults to `Printable`.
+    pub charset: Charset,
+}
+
+impl Default for GenOptions {
+    fn default() -> Self {
+        GenOptions {
+            length: 20,
+            charset: Charset::Printable,
+        }
+    }
+}
+
+/// What a depot records about a key besides its value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    /// The time the value was last modified, as a Unix timestamp.
+    pub modified: Option<i64>,
+
+    /// The time at which the value expires, as a Unix timestamp,
+    /// or None if it does not expire.
+    pub expires: Option<i64>,
+
+    /// The time the value was last fetched, as a Unix timestamp, or None if
+    /// it has not been fetched since the depot began tracking access.
+    pub accessed: Option<i64>,
+
+    /// The number of times the value has been fetched while the depot was
+    /// tracking access.
+    pub access_count: u64,
+}
+
+/// What a depot records about a value, returned along with it by
+/// `fetch_with_meta`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryMeta {
+    /// The time the value was last modified, as a Unix timestamp.
+    pub modified: Option<i64>,
+
+    /// The time at which the value expires, as a Unix timestamp,
+    /// or None if it does not expire.
+    pub expires: Option<i64>,
+
+    /// Whether the value is encrypted.
+    pub encrypted: bool,
+}
+
+/// A previous version of a value, kept when the value was replaced.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryVersion {
+    /// The version number, by which the version can be restored. Versions of
+    /// a key are numbered in the order they were replaced, starting from 1.
+    pub version: u64,
+
+    /// The time the version was stored, as a Unix timestamp.
+    pub modified: Option<i64>,
+
+    /// Whether the version is encrypted.
+    pub encrypted: bool,
+}
+
+/// A value exactly as it is stored, returned by `fetch_raw`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawEntry {
+    /// The stored value: the ciphertext if the value is encrypted, or else
+    /// the compressed bytes if it is compressed, in the depot's encoding, and
+    /// otherwise the value itself.
+    pub val: String,
+
+    /// The nonce the value was encrypted with, or None if it is not
+    /// encrypted.
+    pub nonce: Option<Vec<u8>>,
+
+    /// Whether the value is encrypted.
+    pub encrypted: bool,
+
+    /// Whether the value was compressed before it was encrypted or encoded.
+    pub compressed: bool,
+
+    /// Whether the value is encrypted with a custom password rather than
+    /// the master password.
+    pub custom: bool,
+
+    /// Whether the ciphertext is bound to its key, which values encrypted
+    /// before binding was introduced are not.
+    pub bound: bool,
+
+    /// The hash with which the value's encryption key was derived.
+    pub kdf: KdfHash,
+
+    /// The number of PBKDF2 iterations with which the value's encryption
+    /// key was derived.
+    pub iterations: u32,
+}
+
+/// Which columns of a CSV file `import_csv` reads each key and value from,
+/// named as in the file's header row. The default suits the CSV exported by
+/// most password managers, whose columns include `name`, `url`, `username`,
+/// and `password`.
+#[cfg(feature = "csv")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsvMapping {
+    /// The column holding each key, `name` by default.
+    pub key: String,
+
+    /// The column holding each value, `password` by default.
+    pub value: String,
+}
+
+#[cfg(feature = "csv")]
+impl Default for CsvMapping {
+    fn default() -> CsvMapping {
+        CsvMapping {
+            key: String::from("name"),
+            value: String::from("password"),
+        }
+    }
+}
+
+/// What `merge` does with a key that exists in both depots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// Keep the value already in the depot being merged into.
+    Skip,
+
+    /// Replace it with the value from the depot being merged from.
+    Overwrite,
+
+    /// Keep whichever value was modified more recently, the value already in
+    /// the depot being merged into if they were modified at the same time.
+    KeepNewer,
+}
+
+/// The keys affected by a merge, each list in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// The keys that were only in the depot merged from and were added.
+    pub added: Vec<String>,
+
+    /// The keys in both depots whose values were replaced.
+    pub updated: Vec<String>,
+
+    /// The keys in both depots whose values were kept.
+    pub skipped: Vec<String>,
+}
+
+/// A pool of connections to one database, from which a depot can be made.
+#[cfg(feature = "pool")]
+pub type Pool = r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>;
+
+/// A depot backed by either a single database connection or, with the `pool`
+/// feature, a pool of connections. A single connection is guarded by a mutex,
+/// so a depot may be shared between threads, e.g. behind an `Arc`, but
+/// operations on it run one at a time. A pooled depot runs operations from
+/// different threads in parallel on different connections.
+pub struct Depot {
+    db: Backend,
+    salt: RwLock<Zeroizing<Vec<u8>>>,
+    codec: Codec,
+    opts: Options,
+    rng: Mutex<Box<dyn RngCore + Send>>,
+    store: String,
+    keys: KeyCache,
+    unlocked: Option<Zeroizing<String>>,
+}
+
+/// Encryption keys derived from passwords, each found by an HMAC of the
+/// password, salt, and KDF it was derived with under a secret random to the
+/// depot, so that the cache holds no cheap hash of a password. The HMACs and
+/// keys are wiped from memory when removed.
+struct KeyCache {
+    secret: Secret,
+    keys: Mutex<Vec<(Secret, Secret)>>,
+}
+
+/// A 32-byte secret, wiped from memory when dropped.
+type Secret = Zeroizing<[u8; 32]>;
+
+impl KeyCache {
+    /// Returns an empty cache with a secret drawn from the given generator.
+    fn new(rng: &mut dyn RngCore) -> KeyCache {
+        let mut secret = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(&mut *secret);
+        KeyCache {
+            secret,
+            keys: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the key derived from the given password and salt with the
+    /// given KDF, deriving and remembering it if it is not remembered yet.
+    fn get(&self, password: &[u8], salt: &[u8], kdf: Kdf) -> Secret {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&*self.secret)
+            .expect("HMAC takes keys of any length");
+        mac.update(&(password.len() as u64).to_le_bytes());
+        mac.update(password);
+        mac.update(salt);
+        mac.update(&[kdf.hash as u8]);
+        mac.update(&kdf.iterations.to_le_bytes());
+        let id = Zeroizing::new(<[u8; 32]>::from(mac.finalize().into_bytes()));
+
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, key)) = keys.iter().find(|(i, _)| i.ct_eq(&*id).into()) {
+            return key.clone();
+        }
+        let key = derive_key(password, salt, kdf);
+        keys.push((id, key.clone()));
+        key
+    }
+
+    /// Wipes every remembered key, and its HMAC, from memory.
+    fn clear(&self) {
+        self.keys.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+/// Where a depot gets its database connections from.
+enum Backend {
+    Single(Mutex<rusqlite::Connection>),
+    #[cfg(feature = "pool")]
+    Pooled(Pool),
+}
+
+/// A database connection borrowed from a depot's backend for one operation,
+/// along with the prefix the depot's tables carry.
+struct Conn<'a> {
+    db: Handle<'a>,
+    prefix: &'a str,
+}
+
+/// A database connection borrowed from a depot's backend.
+enum Handle<'a> {
+    Single(MutexGuard<'a, rusqlite::Connection>),
+    #[cfg(feature = "pool")]
+    Pooled(r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>),
+}
+
+impl Deref for Conn<'_> {
+    type Target = rusqlite::Connection;
+
+    fn deref(&self) -> &rusqlite::Connection {
+        match &self.db {
+            Handle::Single(c) => c,
+            #[cfg(feature = "pool")]
+            Handle::Pooled(c) => c,
+        }
+    }
+}
+
+impl Conn<'_> {
+    /// Begins a transaction that is rolled back unless it is committed,
+    /// or returns an error if unsuccessful.
+    fn unchecked_transaction(&self) -> rusqlite::Result<Tx<'_>> {
+        Ok(Tx {
+            tx: (**self).unchecked_transaction()?,
+            prefix: self.prefix,
+        })
+    }
+}
+
+impl Sql for Conn<'_> {
+    fn connection(&self) -> &rusqlite::Connection {
+        self
+    }
+
+    fn prefix(&self) -> &str {
+        self.prefix
+    }
+}
+
+impl Depot {
+    /// Returns a new storage medium (sqlite3 database)
+    /// or an error if initialization is unsuccessful.
+    pub fn new(path: &str) -> Result<Depot> {
+        Depot::with_options(path, Options::default())
+    }
+
+    /// Returns a new storage medium (sqlite3 database) as with `new` at the
+    /// given path, which need not be valid UTF-8, or an error if
+    /// initialization is unsuccessful.
+    pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Depot> {
+        let path = path.as_ref();
+        let conn = rusqlite::Connection::open(path)?;
+        Depot::connect(
+            &path.to_string_lossy(),
+            conn,
+            Options::default(),
+            rand::rngs::OsRng,
+            true,
+        )
+    }
+
+    /// Returns a new storage medium (sqlite3 database) using the given
+    /// options or an error if initialization is unsuccessful. The database
+    /// is opened in WAL mode so that readers and a writer in different
+    /// processes don't block one another.
+    pub fn with_options(path: &str, opts: Options) -> Result<Depot> {
+        Depot::with_rng(path, opts, rand::rngs::OsRng)
+    }
+
+    /// Returns a new storage medium (sqlite3 database) as with `with_options`
+    /// that draws its salt, nonces, and generated values from the given random
+    /// number generator instead of the operating system's. This is meant for
+    /// reproducible tests with a seeded generator; a depot should otherwise
+    /// always use the default.
+    pub fn with_rng<R>(path: &str, opts: Options, rng: R) -> Result<Depot>
+    where
+        R: RngCore + CryptoRng + Send + 'static,
+    {
+        opts.validate()?;
+        let conn = rusqlite::Connection::open(path)?;
+        Depot::connect(path, conn, opts, rng, true)
+    }
+
+    /// Returns a new storage medium (sqlite3 database) as with `new` whose
+    /// entire file, keys and hints included, is encrypted by SQLCipher with
+    /// the given database password, or an error, `BadPassword` if the
+    /// database exists and the password does not open it.
+    #[cfg(feature = "sqlcipher")]
+    pub fn new_encrypted(path: &str, db_password: &str) -> Result<Depot> {
+        let conn = rusqlite::Connection::open(path)?;
+        conn.pragma_update(None, "key", db_password)?;
+        match conn.query_row("select count(*) from sqlite_master", (), |_| Ok(())) {
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::NotADatabase =>
+            {
+                return Err(Error::BadPassword);
+            }
+            r => r?,
+        }
+        Depot::connect(path, conn, Options::default(), rand::rngs::OsRng, true)
+    }
+
+    /// Returns a new storage medium (sqlite3 database) as with `new` whose
+    /// keys belong to the named store, so that one database can hold several
+    /// independent sets of keys, e.g. work and personal. Every store in a
+    /// database shares its salt and master password. The store named by the
+    /// empty string is the one that `new` uses.
+    pub fn new_named(path: &str, store: &str) -> Result<Depot> {
+        let mut depot = Depot::new(path)?;
+        depot.store = String::from(store);
+        Ok(depot)
+    }
+
+    /// Returns a new storage medium (sqlite3 database) as with `new` whose
+    /// tables are named with the given prefix, as with the `table_prefix`
+    /// option, or an error if the prefix is not a valid identifier.
+    pub fn with_table_prefix(path: &str, prefix: &str) -> Result<Depot> {
+        Depot::with_options(
+            path,
+            Options {
+                table_prefix: String::from(prefix),
+                ..Options::default()
+            },
+        )
+    }
+
+    /// Returns the existing depot at the given path or an error, `NoDepot`
+    /// if there is no depot there. Unlike `new`, this never creates a depot,
+    /// so a mistyped path is an error rather than a new, empty depot.
+    pub fn open(path: &str) -> Result<Depot> {
+        Depot::open_with_options(path, Options::default())
+    }
+
+    /// Returns the existing depot at the given path using the given options,
+    /// as with `open`.
+    pub fn open_with_options(path: &str, opts: Options) -> Result<Depot> {
+        opts.validate()?;
+        if !Path::new(path).is_file() {
+            return Err(Error::NoDepot(String::from(path)));
+        }
+
+        let conn = rusqlite::Connection::open_with_flags(
+            path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE
+                | rusqlite::OpenFlags::SQLITE_OPEN_URI
+                | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        Depot::connect(path, conn, opts, rand::rngs::OsRng, false)
+    }
+
+    /// Returns a depot using the given connection to the database at the
+    /// given path or an error if initialization is unsuccessful or, unless
+    /// a depot is to be created, the database is not already a depot.
+    fn connect<R>(
+        path: &str,
+        mut conn: rusqlite::Connection,
+        opts: Options,
+        mut rng: R,
+        create: bool,
+    ) -> Result<Depot>
+    where
+        R: RngCore + CryptoRng + Send + 'static,
+    {
+        conn.busy_timeout(opts.busy_timeout)?;
+        let prefix = &opts.table_prefix;
+        if !create && !has_salt(&conn, prefix)? {
+            return Err(Error::NoDepot(String::from(path)));
+        }
+
+        conn.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        let (salt, codec) = init(&mut conn, &mut rng, &opts)?;
+        if opts.prune_on_open {
+            prune_all(&conn, prefix)?;
+        }
+
+        let keys = KeyCache::new(&mut rng);
+        Ok(Depot {
+            db: Backend::Single(Mutex::new(conn)),
+            salt: RwLock::new(Zeroizing::new(salt)),
+            codec,
+            opts,
+            rng: Mutex::new(Box::new(rng)),
+            store: String::new(),
+            keys,
+            unlocked: None,
+        })
+    }
+
+    /// Returns a depot drawing its connections from the given pool or an
+    /// error if initialization is unsuccessful. The schema and salt are set up
+    /// and loaded once, here. The busy timeout in the options does not apply
+    /// to pooled connections; set one in the connection manager's init hook.
+    #[cfg(feature = "pool")]
+    pub fn from_pool(pool: Pool, opts: Options) -> Result<Depot> {
+        opts.validate()?;
+        let mut conn = pool.get()?;
+        conn.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        let prefix = &opts.table_prefix;
+        let (salt, codec) = init(&mut conn, &mut rand::rngs::OsRng, &opts)?;
+        if opts.prune_on_open {
+            prune_all(&conn, prefix)?;
+        }
+        drop(conn);
+
+        Ok(Depot {
+            db: Backend::Pooled(pool),
+            salt: RwLock::new(Zeroizing::new(salt)),
+            codec,
+            opts,
+            rng: Mutex::new(Box::new(rand::rngs::OsRng)),
+            store: String::new(),
+            keys: KeyCache::new(&mut rand::rngs::OsRng),
+            unlocked: None,
+        })
+    }
+
+    /// Stores the specified key and value in the depot. If the key exists
+    /// then the value is updated. If a password is given it will be used to
+    /// encrypt the value. The first password ever used for encryption becomes
+    /// the master password; values encrypted with any other password are
+    /// marked as using a custom password. The value is compressed first if
+    /// the depot's options call for it. Returns an error if the key is
+    /// invalid or compression, encryption, or storage fails.
+    pub fn stow(&self, key: &str, val: &str, password: Option<&str>) -> Result<()> {
+        let sealed = self.seal(key, val, password)?;
+        self.write(key, &sealed, None, None, None, true)
+    }
+
+    /// Stores the specified key and value in the depot as with `stow`, but
+    /// only if the key does not already exist, so that an existing value is
+    /// never replaced by accident. Returns `AlreadyExists` if it does, and
+    /// otherwise the same errors as `stow`. A key in the trash does not
+    /// count as existing and is replaced.
+    pub fn stow_new(&self, key: &str, val: &str, password: Option<&str>) -> Result<()> {
+        // Checking first keeps a new password from becoming the master
+        // password when nothing is stowed; the write checks again.
+        match self.stored(key) {
+            Ok(_) => return Err(Error::AlreadyExists(String::from(key))),
+            Err(Error::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+        let sealed = self.seal(key, val, password)?;
+        self.write(key, &sealed, None, None, None, false)
+    }
+
+    /// Stores the specified key and value in the depot as with `stow`,
+    /// setting the Unix timestamp at which the value expires, if given.
+    /// Fetching an expired value is an `Expired` error, and `prune_expired`
+    /// deletes expired values. Storing a value replaces any expiration the
+    /// previous value had.
+    pub fn stow_expiring(
+        &self,
+        key: &str,
+        val: &str,
+        password: Option<&str>,
+        expires: Option<i64>,
+    ) -> Result<()> {
+        let sealed = self.seal(key, val, password)?;
+        self.write(key, &sealed, expires, None, None, true)
+    }
+
+    /// Stores the specified key and value in the depot as with `stow`, along
+    /// with a hint to the password it is encrypted with, which `hint` returns
+    /// without a password. The hint itself is stored unencrypted, so it must
+    /// not give the password away. Storing a value replaces any hint the
+    /// previous value had.
+    pub fn stow_with_hint(
+        &self,
+        key: &str,
+        val: &str,
+        password: Option<&str>,
+        hint: &str,
+    ) -> Result<()> {
+        let sealed = self.seal(key, val, password)?;
+        self.write(key, &sealed, None, Some(hint), None, true)
+    }
+
+    /// Stores the specified key and value in the depot as with `stow`, but
+    /// recording the given Unix timestamp, e.g. one kept in a backup, as when
+    /// it was last modified rather than the current time.
+    pub fn stow_with_modified(
+        &self,
+        key: &str,
+        val: &str,
+        password: Option<&str>,
+        modified: i64,
+    ) -> Result<()> {
+        let sealed = self.seal(key, val, password)?;
+        self.write(key, &sealed, None, None, Some(modified), true)
+    }
+
+    /// Returns the unencrypted hint to the password the value associated with
+    /// the specified key is encrypted with, if it was stowed with one, or an
+    /// error if the key does not exist.
+    pub fn hint(&self, key: &str) -> Result<Option<String>> {
+        self.conn()?
+            .query_row(
+                "select hint
+                from storage
+                where store = ?1 and deleted is null and key = ?2",
+                (&self.store, key),
+                |row| row.get(0),
+            )
+            .map_err(|e| lookup_err(key, e))
+    }
+
+    /// Adds the given tags, e.g. `work` or `banking`, to the specified key,
+    /// so that keys can be grouped regardless of how they are named, or
+    /// returns an error if the key does not exist or a tag is empty or
+    /// contains a comma. Like hints, tags are stored unencrypted, so they
+    /// must not give anything secret away. A key keeps its tags when its
+    /// value is replaced.
+    pub fn tag(&self, key: &str, tags: &[&str]) -> Result<()> {
+        if let Some(t) = tags.iter().find(|t| t.trim().is_empty() || t.contains(',')) {
+            return Err(Error::from(format!(
+                "invalid tag '{}': tags must not be empty or contain commas",
+                t
+            )));
+        }
+        self.retag(key, |set| set.extend(tags.iter().map(|t| String::from(*t))))
+    }
+
+    /// Removes the given tags from the specified key, if it has them, or
+    /// returns an error if the key does not exist.
+    pub fn untag(&self, key: &str, tags: &[&str]) -> Result<()> {
+        self.retag(key, |set| set.retain(|t| !tags.contains(&t.as_str())))
+    }
+
+    /// Returns the tags of the specified key, in order, or an error if the
+    /// key does not exist.
+    pub fn tags(&self, key: &str) -> Result<Vec<String>> {
+        let tags: Option<String> = self
+            .conn()?
+            .query_row(
+                "select tags
+                from storage
+                where store = ?1 and deleted is null and key = ?2",
+                (&self.store, key),
+                |row| row.get(0),
+            )
+            .map_err(|e| lookup_err(key, e))?;
+        Ok(split_tags(tags.as_deref()).into_iter().collect())
+    }
+
+    /// Returns every key with the given tag, in order, or an error if
+    /// unsuccessful. No password is needed since no value is read.
+    pub fn keys_by_tag(&self, tag: &str) -> Result<Vec<String>> {
+        Ok(self
+            .conn()?
+            .prepare(
+                "select key from storage
+                where store = ?1 and deleted is null
+                    and instr(',' || tags || ',', ',' || ?2 || ',') > 0
+                order by key",
+            )?
+            .query_map((&self.store, tag), |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Replaces the tags of the specified key with those the given function
+    /// makes of them, or returns an error if the key does not exist.
+    fn retag(&self, key: &str, change: impl Fn(&mut BTreeSet<String>)) -> Result<()> {
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            let tags: Option<String> = tx
+                .query_row(
+                    "select tags
+                    from storage
+                    where store = ?1 and deleted is null and key = ?2",
+                    (&self.store, key),
+                    |row| row.get(0),
+                )
+                .map_err(|e| lookup_err(key, e))?;
+
+            let mut set = split_tags(tags.as_deref());
+            change(&mut set);
+            let tags = match set.is_empty() {
+                true => None,
+                false => Some(Vec::from_iter(set).join(",")),
+            };
+            tx.execute(
+                "update storage set tags = ?3
+                where store = ?1 and deleted is null and key = ?2",
+                (&self.store, key, tags),
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Returns the specified value compressed and encrypted, as the depot's
+    /// options and the given password call for, ready to be stored with the
+    /// specified key, or an error if the key or value is invalid or
+    /// compression or encryption fails.
+    fn seal(&self, key: &str, val: &str, password: Option<&str>) -> Result<Sealed> {
+        self.validate_key(key)?;
+        self.validate_val(key, val)?;
+        let password = match (password, &self.unlocked) {
+            (None, Some(_)) => match self.stored(key) {
+                Ok(stored) if stored.nonce.is_some() => self.password(None, stored.custom),
+                Ok(_) | Err(Error::NotFound(_)) => None,
+                Err(e) => return Err(e),
+            },
+            _ => password,
+        };
+
+        let packed = if self.opts.compress {
+            compress(val.as_bytes())?
+        } else {
+            None
+        };
+        let compressed = packed.is_some();
+        let bytes = packed.as_deref().unwrap_or(val.as_bytes());
+
+        let kdf = self.kdf();
+        let (data, nonce, custom) = match password {
+            None if compressed => (self.codec.encode(bytes), None, false),
+            None => (String::from(val), None, false),
+            Some(p) => {
+                let (c, n) = encrypt(
+                    &mut **self.rng(),
+                    &self.key(p.as_bytes(), &self.salt(), kdf),
+                    bytes,
+                    key.as_bytes(),
+                )?;
+                (self.codec.encode(c), Some(n), !self.claim_master(p)?)
+            }
+        };
+
+        Ok(Sealed {
+            bound: nonce.is_some(),
+            data,
+            nonce,
+            custom,
+            compressed,
+            kdf,
+        })
+    }
+
+    /// Stores the specified key and already encrypted or encoded value in
+    /// the depot along with the given expiration and hint, keeping the given
+    /// modification time or else the current time, and keeping the value's
+    /// previous version if the depot's options call for it. Returns
+    /// `AlreadyExists` if the key exists and is not to be overwritten.
+    fn write(
+        &self,
+        key: &str,
+        sealed: &Sealed,
+        expires: Option<i64>,
+        hint: Option<&str>,
+        modified: Option<i64>,
+        overwrite: bool,
+    ) -> Result<()> {
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            if !overwrite
+                && tx
+                    .query_row(
+                        "select 1 from storage where store = ?1 and deleted is null and key = ?2",
+                        (&self.store, key),
+                        |_| Ok(()),
+                    )
+                    .optional()?
+                    .is_some()
+            {
+                return Err(Error::AlreadyExists(String::from(key)));
+            }
+            journal(&tx, &self.store, key)?;
+            if self.opts.history_limit > 0 {
+                snapshot(&tx, &self.store, key)?;
+                trim_history(&tx, &self.store, key, self.opts.history_limit)?;
+            }
+            tx.prepare_cached(
+                "insert into storage
+                        (modified, key, val, nonce, custom, compressed, bound, kdf, iterations,
+                        store, expires, hint)
+                    values (coalesce(?11, strftime('%s', 'now')), ?1, ?2, ?3, ?4, ?5, ?12,
+                        ?6, ?7, ?8, ?9, ?10)
+                    on conflict (store, key) do
+                    update set
+                        modified = coalesce(?11, strftime('%s', 'now')),
+                        val = ?2,
+                        nonce = ?3,
+                        custom = ?4,
+                        compressed = ?5,
+                        bound = ?12,
+                        kdf = ?6,
+                        iterations = ?7,
+                        expires = ?9,
+                        hint = ?10,
+                        tags = case when deleted is null then tags end,
+                        deleted = null",
+            )?
+            .execute((
+                key,
+                &sealed.data,
+                &sealed.nonce,
+                sealed.custom,
+                sealed.compressed,
+                sealed.kdf.hash,
+                sealed.kdf.iterations,
+                &self.store,
+                expires,
+                hint,
+                modified,
+                sealed.bound,
+            ))?;
+
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Stores the specified key and an entry exactly as `fetch_raw` returns
+    /// it, replacing any existing value, or returns an error if unsuccessful
+    /// or the entry's fields contradict one another.
+    pub fn stow_raw(&self, key: &str, entry: &RawEntry) -> Result<()> {
+        self.validate_key(key)?;
+        self.validate_val(key, &entry.val)?;
+        if entry.encrypted != entry.nonce.is_some() {
+            return Err(Error::from(format!(
+                "the entry for {} must have a nonce if and only if it is encrypted",
+                key
+            )));
+        }
+        if !entry.encrypted && (entry.custom || entry.bound) {
+            return Err(Error::from(format!(
+                "the entry for {} has a password or key binding but is not encrypted",
+                key
+            )));
+        }
+        if entry.iterations == 0 {
+            return Err(Error::from(format!(
+                "the entry for {} has zero KDF iterations",
+                key
+            )));
+        }
+        if let Some(n) = &entry.nonce {
+            if n.len() != NONCE_LEN {
+                return Err(Error::from(format!(
+                    "the nonce for {} is {} bytes rather than {}",
+                    key,
+                    n.len(),
+                    NONCE_LEN
+                )));
+            }
+        }
+        if entry.encrypted || entry.compressed {
+            let what = if entry.encrypted {
+                "ciphertext"
+            } else {
+                "compressed value"
+            };
+            let bytes = self
+                .codec
+                .decode(&entry.val)
+                .map_err(|e| Error::from(format!("the {} for {} is {}", what, key, e)))?;
+            if !entry.encrypted {
+                decompress(&bytes).map_err(|e| {
+                    Error::from(format!(
+                        "the compressed value for {} does not decompress: {}",
+                        key, e
+                    ))
+                })?;
+            }
+        }
+
+        let sealed = Sealed {
+            data: entry.val.clone(),
+            nonce: entry.nonce.clone(),
+            custom: entry.custom,
+            compressed: entry.compressed,
+            bound: entry.bound,
+            kdf: Kdf {
+                hash: entry.kdf,
+                iterations: entry.iterations,
+            },
+        };
+        self.write(key, &sealed, None, None, None, true)
+    }
+
+    /// Returns the value from the depot associated with the specified key
+    /// or an error if unsuccessful. A password must be supplied for
+    /// encrypted values. If the password fails to decrypt the value, the
+    /// error is `DecryptFailed` if the password is nonetheless the master
+    /// password the value was encrypted with, and `BadPassword` otherwise.
+    pub fn fetch(&self, key: &str, password: Option<&str>) -> Result<String> {
+        let stored = self.stored(key)?;
+        let val = self.read_stored(key, &stored, password)?;
+        self.record_access(key)?;
+        Ok(val)
+    }
+
+    /// Returns the value associated with the specified key as with `fetch`,
+    /// along with when it was last modified, when it expires, and whether it
+    /// is encrypted, all read at once, or an error as with `fetch`.
+    pub fn fetch_with_meta(
+        &self,
+        key: &str,
+        password: Option<&str>,
+    ) -> Result<(String, EntryMeta)> {
+        let (stored, modified): (Stored, Option<i64>) = self
+            .conn()?
+            .prepare_cached(
+                "select val, nonce, custom, compressed, bound, kdf, iterations, expires, modified
+                from storage
+                where store = ?1 and deleted is null and key = ?2",
+            )?
+            .query_row((&self.store, key), |row| {
+                Ok((Stored::from_row(row, 0)?, row.get(8)?))
+            })
+            .map_err(|e| lookup_err(key, e))?;
+        let val = self.read_stored(key, &stored, password)?;
+        self.record_access(key)?;
+        let meta = EntryMeta {
+            modified,
+            expires: stored.expires,
+            encrypted: stored.nonce.is_some(),
+        };
+        Ok((val, meta))
+    }
+
+    /// Returns the value associated with the specified key as with `fetch`,
+    /// or the given default if the key does not exist or has expired. Every
+    /// other error, such as `NeedPassword` or `BadPassword`, is returned.
+    pub fn fetch_or(&self, key: &str, password: Option<&str>, default: &str) -> Result<String> {
+        match self.fetch(key, password) {
+            Err(Error::NotFound(_) | Error::Expired(_)) => Ok(String::from(default)),
+            result => result,
+        }
+    }
+
+    /// Returns each of the specified keys paired with its value or the error
+    /// fetching it would return, as with `fetch`, in the order given, or an
+    /// error if the values cannot be read. The values are read in one query,
+    /// and a failure to decrypt one value does not affect the others.
+    pub fn fetch_many(
+        &self,
+        keys: &[&str],
+        password: Option<&str>,
+    ) -> Result<Vec<(String, Result<String>)>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let sql = format!(
+            "select key, val, nonce, custom, compressed, bound, kdf, iterations, expires
+            from storage
+            where store = ? and deleted is null and key in ({})",
+            vec!["?"; keys.len()].join(", ")
+        );
+        let rows: std::collections::HashMap<String, Stored> = self
+            .conn()?
+            .prepare(&sql)?
+            .query_map(
+                rusqlite::params_from_iter(
+                    std::iter::once(self.store.as_str()).chain(keys.iter().copied()),
+                ),
+                |row| Ok((row.get(0)?, Stored::from_row(row, 1)?)),
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(keys
+            .iter()
+            .map(|&key| {
+                let val = match rows.get(key) {
+                    Some(stored) => self.read_stored(key, stored, password).and_then(|v| {
+                        self.record_access(key)?;
+                        Ok(v)
+                    }),
+                    None => Err(Error::NotFound(String::from(key))),
+                };
+                (String::from(key), val)
+            })
+            .collect())
+    }
+
+    /// Returns whether the given password decrypts the encrypted values
+    /// in the depot or an error if the check could not be carried out.
+    /// The password is checked against the sentinel written when the first
+    /// value was encrypted or, in depots that predate the sentinel, against
+    /// a value in this store encrypted with the master password. If there is
+    /// neither, there is nothing to check the password against, and Ok(true)
+    /// is returned for any password: it then means only that nothing in the
+    /// depot contradicts the password, not that it is the master password.
+    pub fn verify_password(&self, password: &str) -> Result<bool> {
+        if let Some((val, nonce, kdf)) = self.sentinel()? {
+            return self.opens_sentinel(&val, &nonce, kdf, password);
+        }
+
+        let row: Option<(String, String, Vec<u8>, bool, Kdf)> = self
+            .conn()?
+            .query_row(
+                "select key, val, nonce, bound, kdf, iterations
+                from storage
+                where store = ?1 and deleted is null and nonce is not null and custom = 0
+                limit 1",
+                (&self.store,),
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        kdf_at(row, 4)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        match row {
+            None => Ok(true),
+            Some((key, val, nonce, bound, kdf)) => {
+                self.opens(&key, &val, &nonce, kdf, password, aad(&key, bound))
+            }
+        }
+    }
+
+    /// Attempts to decrypt every encrypted value with the given password and
+    /// returns the keys, in order, of those it fails to decrypt, whether
+    /// because they are damaged or encrypted with another password, or an
+    /// error if the values cannot be read. Nothing in the depot is changed.
+    pub fn check(&self, password: &str) -> Result<Vec<String>> {
+        let rows: Vec<(String, String, Vec<u8>, bool, bool, Kdf)> = self
+            .conn()?
+            .prepare(
+                "select key, val, nonce, compressed, bound, kdf, iterations
+                from storage
+                where store = ?1 and deleted is null and nonce is not null
+                order by key",
+            )?
+            .query_map((&self.store,), |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    kdf_at(row, 5)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(rows
+            .into_iter()
+            .filter(|(key, val, nonce, compressed, bound, kdf)| {
+                match self.unseal(key, val, nonce, *kdf, password, aad(key, *bound)) {
+                    Ok(txt) => *compressed && decompress(&txt).is_err(),
+                    Err(_) => true,
+                }
+            })
+            .map(|(key, ..)| key)
+            .collect())
+    }
+
+    /// Returns what the depot records about the specified key besides its
+    /// value or an error if the key does not exist. No password is needed
+    /// since the value is not read.
+    pub fn metadata(&self, key: &str) -> Result<Metadata> {
+        self.conn()?
+            .query_row(
+                "select modified, expires, accessed, access_count
+                from storage
+                where store = ?1 and deleted is null and key = ?2",
+                (&self.store, key),
+                |row| {
+                    Ok(Metadata {
+                        modified: row.get(0)?,
+                        expires: row.get(1)?,
+                        accessed: row.get(2)?,
+                        access_count: row.get(3)?,
+                    })
+                },
+            )
+            .map_err(|e| lookup_err(key, e))
+    }
+
+    /// Returns the value associated with the specified key exactly as it is
+    /// stored, whether or not it has expired, or an error if the key does
+    /// not exist. Nothing is decrypted, so no password is needed, and the
+    /// value returned is ciphertext if the value is encrypted.
+    pub fn fetch_raw(&self, key: &str) -> Result<RawEntry> {
+        let stored = self.stored(key)?;
+        Ok(RawEntry {
+            encrypted: stored.nonce.is_some(),
+            val: stored.val,
+            nonce: stored.nonce,
+            compressed: stored.compressed,
+            custom: stored.custom,
+            bound: stored.bound,
+            kdf: stored.kdf.hash,
+            iterations: stored.kdf.iterations,
+        })
+    }
+
+    /// Checks that the given password is the master password and, if it is,
+    /// remembers it and the key derived from it until `lock` is called or
+    /// the depot is dropped. Until then, a value encrypted with the master
+    /// password can be fetched or copied without one, and stowing a value
+    /// without one over such a value keeps it encrypted. Values with a
+    /// custom password still need theirs. Returns `BadPassword` if the
+    /// password is not the master password. A depot with nothing encrypted
+    /// in it has no master password yet to check against, so any password
+    /// unlocks it, as `verify_password` explains.
+    pub fn unlock(&mut self, password: &str) -> Result<()> {
+        if !self.verify_password(password)? {
+            return Err(Error::BadPassword);
+        }
+        self.unlocked = Some(Zeroizing::new(String::from(password)));
+        self.key(password.as_bytes(), &self.salt(), self.kdf());
+        Ok(())
+    }
+
+    /// Wipes the master password remembered by `unlock`, along with every
+    /// encryption key the depot has remembered, from memory.
+    pub fn lock(&mut self) {
+        self.unlocked = None;
+        self.clear_key_cache();
+    }
+
+    /// Returns whether the depot has been unlocked and not locked since.
+    pub fn is_unlocked(&self) -> bool {
+        self.unlocked.is_some()
+    }
+
+    /// Returns the master password the depot is unlocked with, if it is, so
+    /// that new values can be encrypted with it without asking for it again.
+    pub fn unlocked_password(&self) -> Option<&str> {
+        self.unlocked.as_deref().map(String::as_str)
+    }
+
+    /// Wipes every encryption key the depot has remembered, as it does when
+    /// the `cache_keys` option is set, so that each is derived afresh the
+    /// next time it is needed.
+    pub fn clear_key_cache(&self) {
+        self.keys.clear();
+    }
+
+    /// Returns whether the value associated with the specified key is
+    /// encrypted, without needing its password, or an error, `NotFound` if
+    /// the key does not exist, if unsuccessful.
+    pub fn is_encrypted(&self, key: &str) -> Result<bool> {
+        self.conn()?
+            .query_row(
+                "select nonce is not null
+                from storage
+                where store = ?1 and deleted is null and key = ?2",
+                (&self.store, key),
+                |row| row.get(0),
+            )
+            .map_err(|e| lookup_err(key, e))
+    }
+
+    /// Encrypts the unencrypted value associated with the specified key in
+    /// place with the given password, as if it had been stowed with it,
+    /// leaving the time it was last modified alone. Returns an error if the
+    /// key does not exist, the value is already encrypted or changes while
+    /// being encrypted, or encryption fails.
+    pub fn encrypt_entry(&self, key: &str, password: &str) -> Result<()> {
+        let stored = self.stored(key)?;
+        if stored.nonce.is_some() {
+            return Err(Error::from(format!("{} is already encrypted", key)));
+        }
+
+        let packed = match stored.compressed {
+            true => Zeroizing::new(self.decode(key, &stored.val)?),
+            false => Zeroizing::new(Vec::from(stored.val.as_bytes())),
+        };
+        let kdf = self.kdf();
+        let (c, n) = encrypt(
+            &mut **self.rng(),
+            &self.key(password.as_bytes(), &self.salt(), kdf),
+            &packed,
+            key.as_bytes(),
+        )?;
+        let custom = !self.claim_master(password)?;
+
+        let updated = self.retry(|| {
+            Ok(self.conn()?.execute(
+                "update storage
+                set val = ?3, nonce = ?4, custom = ?5, bound = 1, kdf = ?6, iterations = ?7
+                where store = ?1 and deleted is null and key = ?2 and nonce is null and val = ?8",
+                (
+                    &self.store,
+                    key,
+                    self.codec.encode(&c),
+                    &n,
+                    custom,
+                    kdf.hash,
+                    kdf.iterations,
+                    &stored.val,
+                ),
+            )?)
+        })?;
+        match updated {
+            0 => Err(Error::from(format!(
+                "{} changed while being encrypted",
+                key
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decrypts the encrypted value associated with the specified key in
+    /// place with the given password, as if it had been stowed without one,
+    /// leaving the time it was last modified alone. Returns an error if the
+    /// key does not exist, the value is not encrypted or changes while being
+    /// decrypted, or the password does not decrypt it, as with `fetch`.
+    pub fn decrypt_entry(&self, key: &str, password: &str) -> Result<()> {
+        let stored = self.stored(key)?;
+        let nonce = match &stored.nonce {
+            Some(n) => n,
+            None => return Err(Error::from(format!("{} is not encrypted", key))),
+        };
+
+        let txt = match self.unseal(
+            key,
+            &stored.val,
+            nonce,
+            stored.kdf,
+            password,
+            aad(key, stored.bound),
+        ) {
+            Ok(t) => t,
+            Err(e) => return Err(self.unseal_err(key, stored.custom, password, e)?),
+        };
+        let val = match stored.compressed {
+            true => Zeroizing::new(self.codec.encode(&txt)),
+            false => Zeroizing::new(String::from_utf8(txt.to_vec())?),
+        };
+
+        let updated = self.retry(|| {
+            Ok(self.conn()?.execute(
+                "update storage
+                set val = ?3, nonce = null, custom = 0, bound = 0
+                where store = ?1 and deleted is null and key = ?2 and nonce = ?4",
+                (&self.store, key, val.as_str(), nonce),
+            )?)
+        })?;
+        match updated {
+            0 => Err(Error::from(format!(
+                "{} changed while being decrypted",
+                key
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns whether the value associated with the specified key is
+    /// encrypted with a custom password rather than the master password
+    /// or an error if the key does not exist.
+    pub fn has_custom_password(&self, key: &str) -> Result<bool> {
+        self.conn()?
+            .query_row(
+                "select custom
+                from storage
+                where store = ?1 and deleted is null and key = ?2",
+                (&self.store, key),
+                |row| row.get(0),
+            )
+            .map_err(|e| lookup_err(key, e))
+    }
+
+    /// Re-encrypts every value encrypted with the master password, and its
+    /// previous versions, using the new password, which then becomes the master password. Values encrypted
+    /// with a custom password are left untouched. Returns the number of values
+    /// re-encrypted, or an error without changing anything if the old password
+    /// is not the master password or re-encryption fails.
+    pub fn change_password(&self, old: &str, new: &str) -> Result<usize> {
+        if !self.verify_password(old)? {
+            return Err(Error::BadPassword);
+        }
+
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            let n = self.reencrypt(&tx, old, new, self.kdf(), true)?;
+            self.reencrypt_history(&tx, old, new, &self.salt(), true)?;
+            forget_journal(&tx, None)?;
+            self.commit(tx)?;
+            Ok(n)
+        })
+    }
+
+    /// Re-encrypts every master-password value, and the sentinel, whose KDF
+    /// is weaker than the given iteration count and the depot's hash. Returns
+    /// the number re-encrypted, or an error without changing anything if the
+    /// count is zero, the password is not the master password, or
+    /// re-encryption fails.
+    pub fn upgrade_kdf(&self, password: &str, new_iterations: u32) -> Result<usize> {
+        if new_iterations == 0 {
+            return Err(Error::from("KDF iterations must be greater than zero"));
+        }
+        if !self.verify_password(password)? {
+            return Err(Error::BadPassword);
+        }
+
+        let kdf = Kdf {
+            hash: self.opts.kdf_hash,
+            iterations: new_iterations,
+        };
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            let n = self.reencrypt(&tx, password, password, kdf, false)?;
+            self.commit(tx)?;
+            Ok(n)
+        })
+    }
+
+    /// Re-encrypts, in the given transaction, every value encrypted with the
+    /// old password, which must be the master password, using the new
+    /// password and the given KDF, and then the sentinel likewise. Unless
+    /// every value is to be, values already encrypted with the KDF's hash and
+    /// at least its iterations are skipped. Returns the number of values
+    /// re-encrypted or an error if any cannot be.
+    fn reencrypt(&self, tx: &Tx, old: &str, new: &str, kdf: Kdf, every: bool) -> Result<usize> {
+        let rows: Vec<(String, String, Vec<u8>, bool, Kdf, String)> = tx
+            .prepare(
+                "select key, val, nonce, bound, kdf, iterations, store
+                from storage
+                where nonce is not null and custom = 0
+                    and (?1 or kdf != ?2 or iterations < ?3)",
+            )?
+            .query_map((every, kdf.hash, kdf.iterations), |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    kdf_at(row, 4)?,
+                    row.get(6)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        for (key, val, nonce, bound, old_kdf, store) in rows.iter() {
+            // The old password is the master password, so failing to decrypt
+            // a value encrypted with it means the value is damaged.
+            let txt = self
+                .unseal(key, val, nonce, *old_kdf, old, aad(key, *bound))
+                .map_err(|e| match e {
+                    Error::BadPassword => Error::DecryptFailed(key.clone()),
+                    e => e,
+                })?;
+            let (c, n) = encrypt(
+                &mut **self.rng(),
+                &self.key(new.as_bytes(), &self.salt(), kdf),
+                &txt,
+                key.as_bytes(),
+            )?;
+            tx.execute(
+                "update storage
+                set val = ?2, nonce = ?3, bound = 1, kdf = ?4, iterations = ?5
+                where store = ?6 and key = ?1",
+                (
+                    key,
+                    self.codec.encode(c),
+                    n,
+                    kdf.hash,
+                    kdf.iterations,
+                    store,
+                ),
+            )?;
+        }
+
+        let (c, n) = encrypt(
+            &mut **self.rng(),
+            &self.key(new.as_bytes(), &self.salt(), kdf),
+            SENTINEL.as_bytes(),
+            b"",
+        )?;
+        tx.execute(
+            "insert into meta (name, val, nonce, kdf, iterations)
+            values (?1, ?2, ?3, ?4, ?5)
+            on conflict (name) do
+            update set
+                val = ?2,
+                nonce = ?3,
+                kdf = ?4,
+                iterations = ?5",
+            (
+                META_SENTINEL,
+                self.codec.encode(c),
+                n,
+                kdf.hash,
+                kdf.iterations,
+            ),
+        )?;
+        Ok(rows.len())
+    }
+
+    /// Re-encrypts, in the given transaction, every encrypted previous
+    /// version in the history, or only those encrypted with the master
+    /// password, which the old password must then be, using the new password
+    /// and the given salt. With only master-password versions, one the old
+    /// password does not decrypt dates from an earlier master password and is
+    /// marked as custom instead. Returns an error if a version cannot be
+    /// re-encrypted.
+    fn reencrypt_history(
+        &self,
+        tx: &Tx,
+        old: &str,
+        new: &str,
+        salt: &[u8],
+        master_only: bool,
+    ) -> Result<()> {
+        let rows: Vec<(String, String, u64, Stored)> = tx
+            .prepare(
+                "select store, key, version,
+                    val, nonce, custom, compressed, bound, kdf, iterations, null
+                from history
+                where nonce is not null and (custom = 0 or not ?1)",
+            )?
+            .query_map((master_only,), |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    Stored::from_row(row, 3)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let kdf = self.kdf();
+        for (store, key, version, stored) in rows.iter() {
+            let nonce = stored.nonce.as_deref().unwrap_or_default();
+            let aad = aad(key, stored.bound);
+            let txt = match self.unseal(key, &stored.val, nonce, stored.kdf, old, aad) {
+                Err(Error::BadPassword) if master_only => {
+                    tx.execute(
+                        "update history set custom = 1
+                        where store = ?1 and key = ?2 and version = ?3",
+                        (store, key, version),
+                    )?;
+                    continue;
+                }
+                r => r?,
+            };
+            let (c, n) = encrypt(
+                &mut **self.rng(),
+                &self.key(new.as_bytes(), salt, kdf),
+                &txt,
+                key.as_bytes(),
+            )?;
+            tx.execute(
+                "update history
+                set val = ?4, nonce = ?5, bound = 1, kdf = ?6, iterations = ?7
+                where store = ?1 and key = ?2 and version = ?3",
+                (
+                    store,
+                    key,
+                    version,
+                    self.codec.encode(c),
+                    n,
+                    kdf.hash,
+                    kdf.iterations,
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the depot's salt with a newly generated one and re-encrypts
+    /// every encrypted value, its previous versions, and the sentinel, with
+    /// the given password and keys derived from the new salt. Every encrypted
+    /// value and version must be encrypted with the given password. Returns an error without changing anything if
+    /// any value cannot be decrypted or re-encryption fails. Other depots open
+    /// on the same database must be reopened afterward, and no other operation
+    /// should be under way on this depot meanwhile.
+    pub fn rotate_salt(&self, password: &str) -> Result<()> {
+        if !self.verify_password(password)? {
+            return Err(Error::BadPassword);
+        }
+
+        let mut salt = Zeroizing::new(vec![0u8; self.opts.salt_len]);
+        self.rng().fill_bytes(&mut salt);
+        let kdf = self.kdf();
+
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            let rows: Vec<(String, String, Vec<u8>, bool, Kdf, String)> = tx
+                .prepare(
+                    "select key, val, nonce, bound, kdf, iterations, store
+                    from storage
+                    where nonce is not null",
+                )?
+                .query_map((), |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        kdf_at(row, 4)?,
+                        row.get(6)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+
+            for (key, val, nonce, bound, old_kdf, store) in rows.iter() {
+                let txt = self.unseal(key, val, nonce, *old_kdf, password, aad(key, *bound))?;
+                let (c, n) = encrypt(
+                    &mut **self.rng(),
+                    &self.key(password.as_bytes(), &salt, kdf),
+                    &txt,
+                    key.as_bytes(),
+                )?;
+                tx.execute(
+                    "update storage
+                    set val = ?2, nonce = ?3, bound = 1, kdf = ?4, iterations = ?5
+                    where store = ?6 and key = ?1",
+                    (
+                        key,
+                        self.codec.encode(c),
+                        n,
+                        kdf.hash,
+                        kdf.iterations,
+                        store,
+                    ),
+                )?;
+            }
+
+            let (c, n) = encrypt(
+                &mut **self.rng(),
+                &self.key(password.as_bytes(), &salt, kdf),
+                SENTINEL.as_bytes(),
+                b"",
+            )?;
+            tx.execute(
+                "update meta set val = ?2, nonce = ?3, kdf = ?4, iterations = ?5 where name = ?1",
+                (
+                    META_SENTINEL,
+                    self.codec.encode(c),
+                    n,
+                    kdf.hash,
+                    kdf.iterations,
+                ),
+            )?;
+            self.reencrypt_history(&tx, password, password, &salt, false)?;
+            tx.execute("update salt set data = ?1", (salt.as_slice(),))?;
+            forget_journal(&tx, None)?;
+
+            tx.commit()?;
+            *self.salt.write().unwrap_or_else(|e| e.into_inner()) = salt.clone();
+            Ok(())
+        })
+    }
+
+    /// Updates the modified time of the specified key to now without
+    /// changing its value. Returns an error if the key does not exist.
+    pub fn touch(&self, key: &str) -> Result<()> {
+        self.retry(|| {
+            match self.conn()?.execute(
+                "update storage set modified = (strftime('%s', 'now')) where store = ?2 and deleted is null and key = ?1",
+                (key, &self.store),
+            )? {
+                0 => Err(Error::NotFound(String::from(key))),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    /// Returns the keys whose values were modified after the given
+    /// Unix timestamp, in order, or an error if unsuccessful.
+    /// No password is needed since values are not read.
+    pub fn modified_since(&self, unix_ts: i64) -> Result<Vec<String>> {
+        Ok(self
+            .conn()?
+            .prepare(
+                "select key
+                from storage
+                where store = ?2 and deleted is null and modified > ?1
+                order by key",
+            )?
+            .query_map((unix_ts, &self.store), |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Copies the value associated with the src key to the new dst key,
+    /// preserving whether it is encrypted and compressed, its hint, and its
+    /// tags. Since every
+    /// encrypted value needs its own nonce, an encrypted value is decrypted
+    /// and re-encrypted with the given password, which must be supplied.
+    /// Returns an error if src does not exist, dst already exists or is
+    /// invalid, or re-encryption fails.
+    pub fn copy(&self, src: &str, dst: &str, password: Option<&str>) -> Result<()> {
+        self.validate_key(dst)?;
+
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            let (stored, hint, tags): (Stored, Option<String>, Option<String>) = tx
+                .query_row(
+                    "select val, nonce, custom, compressed, bound, kdf, iterations, expires,
+                        hint, tags
+                    from storage
+                    where store = ?1 and deleted is null and key = ?2",
+                    (&self.store, src),
+                    |row| Ok((Stored::from_row(row, 0)?, row.get(8)?, row.get(9)?)),
+                )
+                .map_err(|e| lookup_err(src, e))?;
+
+            if tx
+                .query_row(
+                    "select 1 from storage where store = ?1 and deleted is null and key = ?2",
+                    (&self.store, dst),
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some()
+            {
+                return Err(Error::AlreadyExists(String::from(dst)));
+            }
+            // A key in the trash is replaced, as stowing over it would.
+            tx.execute(
+                "delete from storage where store = ?1 and deleted is not null and key = ?2",
+                (&self.store, dst),
+            )?;
+
+            let (data, nonce) = match (stored.nonce, self.password(password, stored.custom)) {
+                (None, _) => (stored.val, None),
+                (Some(n), Some(p)) => {
+                    let aad = aad(src, stored.bound);
+                    let txt = self.unseal(src, &stored.val, &n, stored.kdf, p, aad)?;
+                    let (c, n) = encrypt(
+                        &mut **self.rng(),
+                        &self.key(p.as_bytes(), &self.salt(), self.kdf()),
+                        &txt,
+                        dst.as_bytes(),
+                    )?;
+                    (self.codec.encode(c), Some(n))
+                }
+                (Some(_), None) => return Err(Error::NeedPassword),
+            };
+
+            tx.execute(
+                "insert into storage
+                    (key, val, nonce, custom, compressed, bound, kdf, iterations, store,
+                    hint, tags)
+                values (?1, ?2, ?3, ?4, ?5, ?3 is not null, ?6, ?7, ?8, ?9, ?10)",
+                (
+                    dst,
+                    data,
+                    nonce,
+                    stored.custom,
+                    stored.compressed,
+                    self.opts.kdf_hash,
+                    self.opts.kdf_iterations,
+                    &self.store,
+                    hint,
+                    tags,
+                ),
+            )?;
+
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Copies every entry in the depot into the other depot, decrypting
+    /// encrypted values with the given password and re-encrypting them with
+    /// it under the other depot's salt. Plaintext values stay plaintext.
+    /// Keys that already exist in the other depot are overwritten, as with
+    /// `stow`, except that each keeps the time it was last modified. Every
+    /// value is decrypted before anything is written, so a value that cannot
+    /// be decrypted, e.g. one with a custom password, fails the copy without
+    /// changing the other depot. Returns the number of entries copied or an
+    /// error if unsuccessful.
+    pub fn copy_to(&self, other: &Depot, password: Option<&str>) -> Result<usize> {
+        let entries = self.entries(password)?;
+        for entry in entries.iter() {
+            let password = if entry.encrypted { password } else { None };
+            let sealed = other.seal(&entry.key, &entry.val, password)?;
+            other.write(&entry.key, &sealed, None, None, entry.modified, true)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Merges every entry in the other depot into this one, decrypting
+    /// encrypted values with the given password and re-encrypting them with
+    /// it under this depot's salt, as with `copy_to`. Keys in both depots are
+    /// resolved according to on_conflict, comparing modified times for
+    /// `KeepNewer`. Values that are added or updated keep the time they were
+    /// last modified in the other depot. Every value in the other depot is
+    /// decrypted before anything is written, so one that cannot be decrypted
+    /// fails the merge without changing this depot. Returns a report of the
+    /// keys added, updated, and skipped or an error if unsuccessful.
+    pub fn merge(
+        &self,
+        other: &Depot,
+        password: Option<&str>,
+        on_conflict: Conflict,
+    ) -> Result<MergeReport> {
+        let entries = other.entries(password)?;
+        let existing: std::collections::HashMap<String, Option<i64>> =
+            self.iter()?.collect::<Result<_>>()?;
+
+        let mut report = MergeReport::default();
+        for entry in entries.iter() {
+            let list = match existing.get(&entry.key) {
+                None => &mut report.added,
+                Some(modified) => match on_conflict {
+                    Conflict::Overwrite => &mut report.updated,
+                    Conflict::KeepNewer if entry.modified > *modified => &mut report.updated,
+                    Conflict::Skip | Conflict::KeepNewer => {
+                        report.skipped.push(entry.key.clone());
+                        continue;
+                    }
+                },
+            };
+            let password = if entry.encrypted { password } else { None };
+            let sealed = self.seal(&entry.key, &entry.val, password)?;
+            self.write(&entry.key, &sealed, None, None, entry.modified, true)?;
+            list.push(entry.key.clone());
+        }
+
+        Ok(report)
+    }
+
+    /// Returns the previous versions of the value associated with the
+    /// specified key that the depot has kept, oldest first, or an error if
+    /// unsuccessful. Versions are only kept if the depot's options call for
+    /// it, and are kept even after the key is dropped, until the depot is
+    /// cleared.
+    pub fn history(&self, key: &str) -> Result<Vec<EntryVersion>> {
+        Ok(self
+            .conn()?
+            .prepare(
+                "select version, modified, nonce is not null
+                from history
+                where store = ?1 and key = ?2
+                order by version",
+            )?
+            .query_map((&self.store, key), |row| {
+                Ok(EntryVersion {
+                    version: row.get(0)?,
+                    modified: row.get(1)?,
+                    encrypted: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Makes the given previous version of the value associated with the
+    /// specified key its current value, keeping the value it replaces as
+    /// another version if the depot keeps versions. An encrypted version is
+    /// restored as it was stored, so it can only be fetched with the password
+    /// it was encrypted with, and not at all if the salt has been rotated
+    /// since. Returns an error, `NotFound` if there is no such version, if
+    /// unsuccessful.
+    pub fn restore(&self, key: &str, version: u64) -> Result<()> {
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            if tx
+                .query_row(
+                    "select 1 from history where store = ?1 and key = ?2 and version = ?3",
+                    (&self.store, key, version),
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_none()
+            {
+                return Err(Error::NotFound(format!("{} version {}", key, version)));
+            }
+
+            if self.opts.history_limit > 0 {
+                snapshot(&tx, &self.store, key)?;
+            }
+            tx.execute(
+                "insert into storage
+                    (key, val, nonce, custom, compressed, bound, kdf, iterations, store)
+                select key, val, nonce, custom, compressed, bound, kdf, iterations, store
+                from history
+                where store = ?1 and key = ?2 and version = ?3
+                on conflict (store, key) do
+                update set
+                    modified = (strftime('%s', 'now')),
+                    val = excluded.val,
+                    nonce = excluded.nonce,
+                    custom = excluded.custom,
+                    compressed = excluded.compressed,
+                    bound = excluded.bound,
+                    kdf = excluded.kdf,
+                    iterations = excluded.iterations,
+                    expires = null,
+                    deleted = null",
+                (&self.store, key, version),
+            )?;
+            // The restored version may be among the oldest, so it is only
+            // trimmed once it has been restored.
+            if self.opts.history_limit > 0 {
+                trim_history(&tx, &self.store, key, self.opts.history_limit)?;
+            }
+
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Deletes every value that has expired. Returns the number of values
+    /// deleted or an error if unsuccessful.
+    pub fn prune_expired(&self) -> Result<usize> {
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            let n = tx.execute(
+                "delete from storage where store = ?1 and expires <= ?2",
+                (&self.store, now()),
+            )?;
+            self.commit(tx)?;
+            Ok(n)
+        })
+    }
+
+    /// Returns every key whose value has expired, in order, as `prune_expired`
+    /// would delete, or an error if unsuccessful.
+    pub fn expired_keys(&self) -> Result<Vec<String>> {
+        Ok(self
+            .conn()?
+            .prepare("select key from storage where store = ?1 and expires <= ?2 order by key")?
+            .query_map((&self.store, now()), |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Deletes the specified key from the depot, or moves it to the trash if
+    /// the depot's options call for it. Returns whether the key existed to be
+    /// deleted or an error if unsuccessful.
+    pub fn drop(&self, key: &str) -> Result<bool> {
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            journal(&tx, &self.store, key)?;
+            let deleted = if self.opts.soft_delete {
+                tx.prepare_cached(
+                    "update storage
+                    set deleted = ?3
+                    where store = ?2 and deleted is null and key = ?1",
+                )?
+                .execute((key, &self.store, now()))?
+            } else {
+                tx.prepare_cached(
+                    "delete from storage where store = ?2 and deleted is null and key = ?1",
+                )?
+                .execute((key, &self.store))?
+            };
+            // Dropping nothing leaves whatever could be undone before.
+            if deleted > 0 {
+                self.commit(tx)?;
+            }
+            Ok(deleted > 0)
+        })
+    }
+
+    /// Reverses the most recent stow or drop of a single key, putting back
+    /// the value, or absence of one, that the key had just beforehand, along
+    /// with its expiration, hint, and tags. Only that one change can be
+    /// undone, and any changes made to the key since are lost with it. Bulk
+    /// changes, such as `drop_matching`, `clear`, and changing the password,
+    /// cannot be undone and leave nothing to undo. Returns the key whose
+    /// change was undone, None if there is nothing to undo, or an error if
+    /// unsuccessful.
+    pub fn undo(&self) -> Result<Option<String>> {
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            let (key, existed): (String, bool) = match tx.query_row(
+                "select key, val is not null from journal where store = ?1",
+                (&self.store,),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            ) {
+                Ok(entry) => entry,
+                Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
 
-impl Depot {
-    /// Returns a new storage medium (sqlite3 database)
-    /// or an error if initialization is unsuccessful.
-    pub fn new(path: &str) -> Result<Depot> {
-        let conn = rusqlite::Connection::open(path)?;
-        match conn.query_row("select data from salt", (), |row| row.get(0)) {
-            Ok(s) => Ok(Depot { db: conn, salt: s }),
-            _ => {
-                let mut d = Depot {
-                    db: conn,
-                    salt: [0u8; 32],
-                };
-                d.init()?;
-                Ok(d)
+            tx.execute(
+                "delete from storage where store = ?1 and key = ?2",
+                (&self.store, &key),
+            )?;
+            if existed {
+                tx.execute(
+                    "insert into storage
+                        (store, key, modified, val, nonce, custom, compressed, bound,
+                        iterations, kdf, expires, accessed, access_count, deleted, hint, tags)
+                    select store, key, modified, val, nonce, custom, compressed, bound,
+                        iterations, kdf, expires, accessed, access_count, deleted, hint, tags
+                    from journal
+                    where store = ?1",
+                    (&self.store,),
+                )?;
+            }
+            forget_journal(&tx, Some(&self.store))?;
+            self.commit(tx)?;
+            Ok(Some(key))
+        })
+    }
+
+    /// Deletes every key matching the given glob pattern from the depot, or
+    /// moves them to the trash, as with `drop`, all at once. The pattern is
+    /// a SQLite glob: `*` matches any text, `?` any one character, and `[...]`
+    /// any one of the enclosed characters, case-sensitively, e.g. `old/*`.
+    /// Returns the number of keys deleted or an error if unsuccessful.
+    pub fn drop_matching(&self, pattern: &str) -> Result<usize> {
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            forget_journal(&tx, Some(&self.store))?;
+            let deleted = if self.opts.soft_delete {
+                tx.execute(
+                    "update storage
+                    set deleted = ?3
+                    where store = ?2 and deleted is null and key glob ?1",
+                    (pattern, &self.store, now()),
+                )?
+            } else {
+                tx.execute(
+                    "delete from storage where store = ?2 and deleted is null and key glob ?1",
+                    (pattern, &self.store),
+                )?
+            };
+            self.commit(tx)?;
+            Ok(deleted)
+        })
+    }
+
+    /// Returns every key matching the given glob pattern, as with
+    /// `drop_matching`, in order, or an error if unsuccessful.
+    pub fn keys_matching(&self, pattern: &str) -> Result<Vec<String>> {
+        Ok(self
+            .conn()?
+            .prepare(
+                "select key from storage
+                where store = ?1 and deleted is null and key glob ?2
+                order by key",
+            )?
+            .query_map((&self.store, pattern), |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Returns the number of keys in the depot or, given a glob pattern, the
+    /// number matching it, as with `keys_matching`, or an error if
+    /// unsuccessful. No password is needed since no value is read.
+    pub fn count(&self, pattern: Option<&str>) -> Result<usize> {
+        Ok(self.conn()?.query_row(
+            "select count(*) from storage
+            where store = ?1 and deleted is null and (?2 is null or key glob ?2)",
+            (&self.store, pattern),
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Brings the specified key back from the trash. Returns an error,
+    /// `NotFound` if the key is not in the trash, if unsuccessful.
+    pub fn undelete(&self, key: &str) -> Result<()> {
+        self.retry(|| {
+            match self.conn()?.execute(
+                "update storage
+                set deleted = null
+                where store = ?1 and deleted is not null and key = ?2",
+                (&self.store, key),
+            )? {
+                0 => Err(Error::NotFound(String::from(key))),
+                _ => Ok(()),
             }
+        })
+    }
+
+    /// Deletes every key in the trash. Returns the number of keys deleted
+    /// or an error if unsuccessful.
+    pub fn empty_trash(&self) -> Result<usize> {
+        self.retry(|| {
+            Ok(self.conn()?.execute(
+                "delete from storage where store = ?1 and deleted is not null",
+                (&self.store,),
+            )?)
+        })
+    }
+
+    /// Generates a random value of the length and from the character set in
+    /// the given options and stores it under the specified key as with
+    /// `stow`. Each character is chosen uniformly, by rejection sampling
+    /// rather than by a biased modulo, using the depot's random number
+    /// generator (the operating system's unless given to `with_rng`), so
+    /// that the value has the full entropy its length and set allow.
+    /// Returns the generated value or an error if the length is zero, the
+    /// set is empty, or storage fails.
+    pub fn generate(
+        &self,
+        key: &str,
+        opts: GenOptions,
+        password: Option<&str>,
+    ) -> Result<Zeroizing<String>> {
+        let chars = opts.charset.chars();
+        if opts.length == 0 {
+            return Err(Error::from("length must be greater than zero"));
+        } else if chars.is_empty() {
+            return Err(Error::from("character set must not be empty"));
         }
+
+        let mut rng = self.rng();
+        let val: Zeroizing<String> = Zeroizing::new(
+            (0..opts.length)
+                .map(|_| chars[uniform_index(&mut **rng, chars.len())])
+                .collect(),
+        );
+        drop(rng);
+        self.stow(key, &val, password)?;
+
+        Ok(val)
     }
 
-    /// Stores the specified key and value in the depot. If the key exists
-    /// then the value is updated. If a password is given it will be used to
-    /// encrypt the value. Returns an error if encryption or storage fails.
-    pub fn stow(&self, key: &str, val: &str, password: Option<&str>) -> Result<()> {
-        let (data, nonce) = match password {
-            None => (String::from(val), None),
-            Some(p) => match encrypt(p.as_bytes(), &self.salt, val.as_bytes()) {
-                Ok((c, n)) => (b64.encode(c), Some(n)),
-                Err(e) => return Err(Error::from(e)),
-            },
+    /// Returns a summary of the depot's contents or an error if unsuccessful.
+    /// No password is needed since values are not read.
+    pub fn stats(&self) -> Result<Stats> {
+        let db = self.conn()?;
+        let (total, encrypted, earliest, latest) = db.query_row(
+            "select count(*), count(nonce), min(modified), max(modified)
+            from storage
+            where store = ?1 and deleted is null",
+            (&self.store,),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        let file_size = db.query_row(
+            "select page_count * page_size
+            from pragma_page_count(), pragma_page_size()",
+            (),
+            |row| row.get(0),
+        )?;
+
+        Ok(Stats {
+            total,
+            encrypted,
+            plaintext: total - encrypted,
+            earliest,
+            latest,
+            file_size,
+        })
+    }
+
+    /// Deletes every key from the depot, along with the previous versions of
+    /// their values, in a single transaction, keeping the salt and master
+    /// password so that the same password can still be used. Returns the
+    /// number of keys deleted or an error if unsuccessful.
+    pub fn clear(&self) -> Result<usize> {
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            let n = tx.execute("delete from storage where store = ?1", (&self.store,))?;
+            tx.execute("delete from history where store = ?1", (&self.store,))?;
+            forget_journal(&tx, Some(&self.store))?;
+            self.commit(tx)?;
+            Ok(n)
+        })
+    }
+
+    /// Copies the entire depot, including its salt and every value, to a new
+    /// database at the given path. SQLite's online backup is used so that the
+    /// copy is consistent even if the depot is written to meanwhile.
+    /// Returns an error if unsuccessful.
+    pub fn backup(&self, dest_path: &str) -> Result<()> {
+        self.conn()?
+            .backup(rusqlite::DatabaseName::Main, dest_path, None)?;
+        Ok(())
+    }
+
+    /// Rebuilds the database file to reclaim the space left behind by deleted
+    /// and updated values. This runs outside of any transaction, as SQLite
+    /// requires. Returns an error if unsuccessful.
+    pub fn compact(&self) -> Result<()> {
+        self.conn()?.execute_batch("vacuum")?;
+        Ok(())
+    }
+
+    /// Returns every key in the depot, in order, or an error if unsuccessful.
+    pub fn keys(&self) -> Result<Vec<String>> {
+        Ok(self
+            .conn()?
+            .prepare("select key from storage where store = ?1 and deleted is null order by key")?
+            .query_map((&self.store,), |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Returns, in order, every key in the depot whose value is encrypted if
+    /// `encrypted` is `Some(true)`, every key whose value is not if it is
+    /// `Some(false)`, and every key if it is None, or an error if
+    /// unsuccessful. No password is needed since no value is read.
+    pub fn keys_filtered(&self, encrypted: Option<bool>) -> Result<Vec<String>> {
+        Ok(self
+            .conn()?
+            .prepare(
+                "select key from storage
+                where store = ?1 and deleted is null
+                    and (?2 is null or (nonce is not null) = ?2)
+                order by key",
+            )?
+            .query_map((&self.store, encrypted), |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Stores the given value serialized as JSON under the specified key
+    /// as with `stow`. Returns an error if serialization or storage fails.
+    #[cfg(feature = "serde")]
+    pub fn stow_value<T: serde::Serialize>(
+        &self,
+        key: &str,
+        val: &T,
+        password: Option<&str>,
+    ) -> Result<()> {
+        self.stow(key, &serde_json::to_string(val)?, password)
+    }
+
+    /// Returns the value associated with the specified key, as with `fetch`,
+    /// deserialized from JSON or an error if the value cannot be fetched or
+    /// is not a JSON representation of the type.
+    #[cfg(feature = "serde")]
+    pub fn fetch_value<T: serde::de::DeserializeOwned>(
+        &self,
+        key: &str,
+        password: Option<&str>,
+    ) -> Result<T> {
+        let json = Zeroizing::new(self.fetch(key, password)?);
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Returns the name of the store the depot's keys belong to,
+    /// which is empty unless the depot was opened with `new_named`.
+    pub fn store(&self) -> &str {
+        &self.store
+    }
+
+    /// Reads CSV with a header row from the given reader and stows the value
+    /// in each row under its key, according to the given mapping, encrypted
+    /// with the given password. Other columns are ignored. Keys that already
+    /// exist are overwritten, as with `stow`, and so a key repeated in the
+    /// file takes its last value. Every row is read before anything is
+    /// stowed, so malformed CSV, a missing column, or an invalid key changes
+    /// nothing. Returns the number of values stowed or an error if
+    /// unsuccessful.
+    #[cfg(feature = "csv")]
+    pub fn import_csv<R: std::io::Read>(
+        &self,
+        reader: R,
+        mapping: &CsvMapping,
+        password: &str,
+    ) -> Result<usize> {
+        let mut reader = csv::Reader::from_reader(reader);
+        let headers = reader.headers().map_err(|e| Error::from(e.to_string()))?;
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| Error::from(format!("CSV has no {} column", name)))
         };
+        let (key_col, val_col) = (column(&mapping.key)?, column(&mapping.value)?);
 
-        self.db.execute(
-            "insert into storage (key, val, nonce)
-            values (?1, ?2, ?3)
-            on conflict (key) do
-            update set
-                modified = (strftime('%s', 'now')),
-                val = ?2,
-                nonce = ?3",
-            (key, data, nonce),
-        )?;
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| Error::from(e.to_string()))?;
+            let key = record.get(key_col).unwrap_or_default();
+            self.validate_key(key)?;
+            let val = Zeroizing::new(String::from(record.get(val_col).unwrap_or_default()));
+            rows.push((String::from(key), val));
+        }
+
+        if self.opts.dry_run {
+            return Ok(rows.len());
+        }
+        for (key, val) in rows.iter() {
+            self.stow(key, val, Some(password))?;
+        }
+        Ok(rows.len())
+    }
+
+    /// Writes every key in the depot and its value, decrypted with the given
+    /// password if it is encrypted, to the given writer as CSV with a
+    /// `name,password` header row, which `import_csv` reads with the default
+    /// mapping. Values are quoted as needed. Every value is decrypted before
+    /// anything is written, so a missing or wrong password (`NeedPassword` or
+    /// `BadPassword`) writes nothing. Returns the number of values written or
+    /// an error if unsuccessful.
+    #[cfg(feature = "csv")]
+    pub fn export_csv<W: std::io::Write>(
+        &self,
+        writer: W,
+        password: Option<&str>,
+    ) -> Result<usize> {
+        let entries = self.entries(password)?;
+        let mapping = CsvMapping::default();
+        let mut writer = csv::Writer::from_writer(writer);
+        writer
+            .write_record([&mapping.key, &mapping.value])
+            .map_err(|e| Error::from(e.to_string()))?;
+        for entry in entries.iter() {
+            writer
+                .write_record([entry.key.as_str(), entry.val.as_str()])
+                .map_err(|e| Error::from(e.to_string()))?;
+        }
+        writer.flush()?;
+        Ok(entries.len())
+    }
+
+    /// Returns the depot's salt, its sentinel, and every key in the depot
+    /// along with its value exactly as stored, encrypted values still
+    /// encrypted, in a portable, versioned format that `import_encrypted`
+    /// reads. No password is needed, since nothing is decrypted, and the same
+    /// password decrypts the values once imported. Plaintext values are
+    /// exported as they are stored, in plaintext. Keys in the trash are left
+    /// out. Returns an error if unsuccessful.
+    pub fn export_encrypted(&self) -> Result<Vec<u8>> {
+        let entries = self
+            .conn()?
+            .prepare(
+                "select key, modified, val, nonce, custom, compressed, bound, kdf, iterations,
+                    expires, hint, tags
+                from storage
+                where store = ?1 and deleted is null
+                order by key",
+            )?
+            .query_map((&self.store,), |row| {
+                Ok(dump::DumpEntry {
+                    key: row.get(0)?,
+                    modified: row.get(1)?,
+                    val: row.get(2)?,
+                    nonce: row.get(3)?,
+                    custom: row.get(4)?,
+                    compressed: row.get(5)?,
+                    bound: row.get(6)?,
+                    kdf: kdf_at(row, 7)?,
+                    expires: row.get(9)?,
+                    hint: row.get(10)?,
+                    tags: row.get(11)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(dump::Dump {
+            salt: self.salt().to_vec(),
+            codec: self.codec,
+            sentinel: self.sentinel()?,
+            tagged: true,
+            entries,
+        }
+        .encode())
+    }
+
+    /// Imports the salt, sentinel, and entries exported by `export_encrypted`
+    /// in one transaction, overwriting existing keys. Keys keep their tags if
+    /// the export, as one made before tags were exported, has none. A depot that already
+    /// has encrypted values can only import an export with the same salt.
+    /// Returns the number of entries imported or an error, changing nothing.
+    pub fn import_encrypted(&self, data: &[u8]) -> Result<usize> {
+        let dump = dump::Dump::decode(data)?;
+        let salt = Zeroizing::new(dump.salt);
+        if salt.len() < MIN_SALT_LEN {
+            return Err(Error::from(format!(
+                "the exported salt is shorter than {} bytes",
+                MIN_SALT_LEN
+            )));
+        }
+        // Encrypted and compressed values are re-encoded in case the depot
+        // uses another encoding or base64 alphabet.
+        let recode = |key: &str, val: &str| -> Result<String> {
+            match dump.codec.decode(val) {
+                Ok(bytes) => Ok(self.codec.encode(bytes)),
+                Err(e) => Err(Error::Malformed(String::from(key), e)),
+            }
+        };
+
+        self.retry(|| {
+            let db = self.conn()?;
+            let tx = db.unchecked_transaction()?;
+            if *salt != *self.salt() {
+                let encrypted: bool = tx.query_row(
+                    "select exists (select 1 from storage where nonce is not null)
+                        or exists (select 1 from history where nonce is not null)",
+                    (),
+                    |row| row.get(0),
+                )?;
+                if encrypted {
+                    return Err(Error::from(
+                        "cannot import an export with another salt into a depot with encrypted values",
+                    ));
+                }
+                tx.execute("update salt set data = ?1", (salt.as_slice(),))?;
+                tx.execute("delete from meta where name = ?1", (META_SENTINEL,))?;
+            }
+
+            if let Some((val, nonce, kdf)) = dump.sentinel.as_ref() {
+                tx.execute(
+                    "insert into meta (name, val, nonce, kdf, iterations)
+                    values (?1, ?2, ?3, ?4, ?5)
+                    on conflict (name) do nothing",
+                    (META_SENTINEL, recode(META_SENTINEL, val)?, nonce, kdf.hash, kdf.iterations),
+                )?;
+            }
+
+            for e in dump.entries.iter() {
+                self.validate_key(&e.key)?;
+                let val = if e.nonce.is_some() || e.compressed {
+                    recode(&e.key, &e.val)?
+                } else {
+                    e.val.clone()
+                };
+                tx.execute(
+                    "insert into storage
+                        (key, modified, val, nonce, custom, compressed, bound, kdf, iterations,
+                        expires, hint, store, tags)
+                    values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+                    on conflict (store, key) do
+                    update set
+                        modified = ?2,
+                        val = ?3,
+                        nonce = ?4,
+                        custom = ?5,
+                        compressed = ?6,
+                        bound = ?7,
+                        kdf = ?8,
+                        iterations = ?9,
+                        expires = ?10,
+                        hint = ?11,
+                        tags = case when ?14 then ?13 when deleted is null then tags end,
+                        deleted = null",
+                    rusqlite::params![
+                        e.key,
+                        e.modified,
+                        val,
+                        e.nonce,
+                        e.custom,
+                        e.compressed,
+                        e.bound,
+                        e.kdf.hash,
+                        e.kdf.iterations,
+                        e.expires,
+                        e.hint,
+                        self.store,
+                        e.tags,
+                        dump.tagged,
+                    ],
+                )?;
+            }
+
+            self.commit(tx)?;
+            if !self.opts.dry_run {
+                *self.salt.write().unwrap_or_else(|e| e.into_inner()) = salt.clone();
+            }
+            Ok(dump.entries.len())
+        })
+    }
+
+    /// Returns a view of the depot in which every key is transparently
+    /// prefixed with the given prefix and a slash, e.g. to keep the keys of
+    /// different components or tenants apart.
+    pub fn scoped(&self, prefix: &str) -> ScopedDepot<'_> {
+        ScopedDepot::new(self, prefix)
+    }
+
+    /// Returns at most limit keys, in order, skipping the first offset keys,
+    /// or an error if unsuccessful. Successive pages only line up with one
+    /// another if no keys are added or removed between reading them.
+    pub fn keys_page(&self, offset: usize, limit: usize) -> Result<Vec<String>> {
+        Ok(self
+            .conn()?
+            .prepare(
+                "select key
+                from storage
+                where store = ?3 and deleted is null
+                order by key
+                limit ?2 offset ?1",
+            )?
+            .query_map((offset, limit, &self.store), |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Returns an iterator over every key in the depot, in order, along with
+    /// the time its value was last modified, or an error if unsuccessful.
+    /// Keys are read a batch at a time rather than all at once, and no values
+    /// are read, so no password is needed.
+    pub fn iter(&self) -> Result<impl Iterator<Item = Result<(String, Option<i64>)>> + '_> {
+        let mut entries = Entries {
+            depot: self,
+            batch: Vec::new().into_iter(),
+            last: None,
+            done: false,
+        };
+        entries.load()?;
+        Ok(entries)
+    }
+
+    /// Returns an iterator over every key in the depot, in order, along with
+    /// its value, decrypted with the given password if it is encrypted, or an
+    /// error if unsuccessful. Each value that cannot be fetched or decrypted
+    /// is yielded as an error in its place without ending the iteration.
+    pub fn iter_decrypted<'a>(
+        &'a self,
+        password: &'a str,
+    ) -> Result<impl Iterator<Item = Result<(String, String)>> + 'a> {
+        Ok(self.iter()?.map(move |entry| {
+            let (key, _) = entry?;
+            let val = self.fetch(&key, Some(password))?;
+            Ok((key, val))
+        }))
+    }
+
+    /// Returns the next batch of keys after the given key, in order,
+    /// along with their modified times, or an error if unsuccessful.
+    fn entries_after(&self, after: Option<&str>) -> Result<Vec<(String, Option<i64>)>> {
+        Ok(self
+            .conn()?
+            .prepare(
+                "select key, modified
+                from storage
+                where store = ?3 and deleted is null and (?1 is null or key > ?1)
+                order by key
+                limit ?2",
+            )?
+            .query_map((after, ITER_BATCH, &self.store), |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Returns every entry in the depot that has not expired, in order, with
+    /// its value decrypted with the given password as needed, or an error if
+    /// any value cannot be read or decrypted.
+    fn entries(&self, password: Option<&str>) -> Result<Vec<Entry>> {
+        let rows: Vec<(String, Option<i64>, Stored)> = self
+            .conn()?
+            .prepare(
+                "select key, modified, val, nonce, custom, compressed, bound, kdf, iterations,
+                    expires
+                from storage
+                where store = ?1 and deleted is null and (expires is null or expires > ?2)
+                order by key",
+            )?
+            .query_map((&self.store, now()), |row| {
+                Ok((row.get(0)?, row.get(1)?, Stored::from_row(row, 2)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
 
+        rows.into_iter()
+            .map(|(key, modified, stored)| {
+                Ok(Entry {
+                    val: Zeroizing::new(self.read_stored(&key, &stored, password)?),
+                    encrypted: stored.nonce.is_some(),
+                    key,
+                    modified,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns an error if the given key is empty, only whitespace,
+    /// or longer than the depot's options allow.
+    pub(crate) fn validate_key(&self, key: &str) -> Result<()> {
+        if key.trim().is_empty() {
+            Err(Error::InvalidKey(String::from("key must not be empty")))
+        } else if key.len() > self.opts.max_key_len {
+            Err(Error::InvalidKey(format!(
+                "key must be at most {} bytes",
+                self.opts.max_key_len
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns an error if the given value for the specified key is longer
+    /// than the depot's options allow.
+    fn validate_val(&self, key: &str, val: &str) -> Result<()> {
+        if val.len() > self.opts.max_value_len {
+            return Err(Error::TooLarge(format!(
+                "{} bytes for {}, more than the limit of {}",
+                val.len(),
+                key,
+                self.opts.max_value_len
+            )));
+        }
         Ok(())
     }
 
-    /// Returns the value from the depot associated with the specified key
-    /// or an error if unsuccessful. A password must be supplied for
-    /// encrypted values.
-    pub fn fetch(&self, key: &str, password: Option<&str>) -> Result<String> {
-        let (val, nonce): (String, Option<Vec<u8>>) = self.db.query_row(
-            "select val, nonce
-            from storage
-            where key = ?",
-            (key,),
-            |row| Ok((row.get(0)?, row.get(1)?)),
-        )?;
+    /// Returns a database connection for the depot's exclusive use until it
+    /// is dropped, waiting for another thread to finish with it if need be,
+    /// or an error if no pooled connection becomes available. A connection
+    /// left behind by a thread that panicked is still usable, since every
+    /// multi-statement write is transactional.
+    fn conn(&self) -> Result<Conn<'_>> {
+        let db = match &self.db {
+            Backend::Single(m) => Handle::Single(m.lock().unwrap_or_else(|e| e.into_inner())),
+            #[cfg(feature = "pool")]
+            Backend::Pooled(p) => Handle::Pooled(p.get()?),
+        };
+        Ok(Conn {
+            db,
+            prefix: &self.opts.table_prefix,
+        })
+    }
+
+    /// Commits the given transaction unless the depot's options call for a
+    /// dry run, in which case it is rolled back. Returns an error if
+    /// unsuccessful.
+    fn commit(&self, tx: Tx) -> rusqlite::Result<()> {
+        if self.opts.dry_run {
+            return Ok(());
+        }
+        tx.commit()
+    }
+
+    /// Returns the result of the given database operation, retrying it, after
+    /// a backoff that doubles each time, as many times as the depot's options
+    /// allow while it fails because the database is busy or locked.
+    fn retry<T>(&self, mut op: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut backoff = self.opts.busy_backoff;
+        for _ in 0..self.opts.busy_retries {
+            match op() {
+                Err(Error::SqlErr(e)) if is_busy(&e) => {
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+        op()
+    }
+
+    /// Returns a copy of the depot's current salt, which is wiped from memory
+    /// when dropped like the depot's own.
+    fn salt(&self) -> Zeroizing<Vec<u8>> {
+        self.salt.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Returns the depot's random number generator, locked for one operation.
+    fn rng(&self) -> MutexGuard<'_, Box<dyn RngCore + Send>> {
+        self.rng.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the given password or, if there is none and the value it is
+    /// for is encrypted with the master password rather than a custom one,
+    /// the master password the depot was unlocked with, if any.
+    fn password<'a>(&'a self, given: Option<&'a str>, custom: bool) -> Option<&'a str> {
+        match (given, &self.unlocked) {
+            (None, Some(p)) if !custom => Some(p.as_str()),
+            _ => given,
+        }
+    }
+
+    /// Returns the encryption key derived from the given password and salt
+    /// with the given KDF, deriving it only the first time if the depot's
+    /// options call for caching keys or the depot is unlocked with it.
+    fn key(&self, password: &[u8], salt: &[u8], kdf: Kdf) -> Zeroizing<[u8; 32]> {
+        let unlocked = self
+            .unlocked
+            .as_ref()
+            .is_some_and(|p| p.as_bytes().ct_eq(password).into());
+        if !self.opts.cache_keys && !unlocked {
+            return derive_key(password, salt, kdf);
+        }
+
+        self.keys.get(password, salt, kdf)
+    }
 
-        match nonce {
-            None => Ok(val),
-            Some(n) => match password {
+    /// Returns the stored value associated with the specified key or an error,
+    /// `NotFound` if the key does not exist, if unsuccessful.
+    fn stored(&self, key: &str) -> Result<Stored> {
+        self.conn()?
+            .prepare_cached(
+                "select val, nonce, custom, compressed, bound, kdf, iterations, expires
+                from storage
+                where store = ?1 and deleted is null and key = ?2",
+            )?
+            .query_row((&self.store, key), |row| Stored::from_row(row, 0))
+            .map_err(|e| lookup_err(key, e))
+    }
+
+    /// Returns the given stored value associated with the specified key,
+    /// decrypted with the given password and decompressed as needed,
+    /// or an error if unsuccessful, as with `fetch`.
+    fn read_stored(&self, key: &str, stored: &Stored, password: Option<&str>) -> Result<String> {
+        if stored.expires.is_some_and(|t| t <= now()) {
+            return Err(Error::Expired(String::from(key)));
+        }
+
+        match &stored.nonce {
+            None if stored.compressed => {
+                let packed = self.decode(key, &stored.val)?;
+                Ok(String::from_utf8(decompress(&packed)?)?)
+            }
+            None => Ok(stored.val.clone()),
+            Some(n) if n.len() != NONCE_LEN => Err(Error::DecryptFailed(String::from(key))),
+            Some(n) => match self.password(password, stored.custom) {
                 Some(p) => {
-                    let valbytes = b64.decode(val)?;
-                    let txt = decrypt(p.as_bytes(), &self.salt, &n, &valbytes)?;
-                    Ok(String::from_utf8(txt)?)
+                    let kdf = stored.kdf;
+                    let txt = match self.unseal(key, &stored.val, n, kdf, p, aad(key, stored.bound))
+                    {
+                        Ok(t) => t,
+                        Err(e) => return Err(self.unseal_err(key, stored.custom, p, e)?),
+                    };
+                    if self.opts.upgrade_on_fetch
+                        && kdf.hash == KdfHash::Sha1
+                        && self.opts.kdf_hash != KdfHash::Sha1
+                    {
+                        self.reseal(key, n, &txt, p)?;
+                    }
+                    if stored.compressed {
+                        Ok(String::from_utf8(decompress(&txt)?)?)
+                    } else {
+                        Ok(String::from_utf8(txt.to_vec())?)
+                    }
                 }
                 None => Err(Error::NeedPassword),
             },
         }
     }
 
-    /// Deletes the specified key from the depot.
-    /// Returns an error is unsuccessful.
-    pub fn drop(&self, key: &str) -> Result<()> {
-        self.db
-            .execute("delete from storage where key = ?1", (key,))?;
+    /// Records that the value associated with the specified key was just
+    /// fetched, if the depot's options call for it. Returns an error if
+    /// unsuccessful.
+    fn record_access(&self, key: &str) -> Result<()> {
+        if self.opts.track_access {
+            self.conn()?
+                .prepare_cached(
+                    "update storage
+                    set accessed = ?3, access_count = access_count + 1
+                    where store = ?1 and key = ?2",
+                )?
+                .execute((&self.store, key, now()))?;
+        }
         Ok(())
     }
 
-    /// Writes the schema to the database.
+    /// Returns the KDF with which values are encrypted from now on.
+    fn kdf(&self) -> Kdf {
+        Kdf {
+            hash: self.opts.kdf_hash,
+            iterations: self.opts.kdf_iterations,
+        }
+    }
+
+    /// Returns the bytes of the given stored value of the specified key
+    /// decrypted with the given password, KDF, and associated data or an
+    /// error if unsuccessful.
+    fn unseal(
+        &self,
+        key: &str,
+        val: &str,
+        nonce: &[u8],
+        kdf: Kdf,
+        password: &str,
+        aad: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>> {
+        let valbytes = self.decode(key, val)?;
+        let txt = decrypt(
+            &self.key(password.as_bytes(), &self.salt(), kdf),
+            nonce,
+            &valbytes,
+            aad,
+        )?;
+        Ok(Zeroizing::new(txt))
+    }
+
+    /// Returns the bytes of the given stored value of the specified key or
+    /// an error, `Malformed`, if it is not validly encoded, which can only
+    /// happen if the database has been damaged or altered.
+    fn decode(&self, key: &str, val: &str) -> Result<Vec<u8>> {
+        self.codec
+            .decode(val)
+            .map_err(|e| Error::Malformed(String::from(key), e))
+    }
+
+    /// Returns the error to report for the given failure to decrypt the value
+    /// associated with the specified key with the given password, or an
+    /// error if that cannot be determined. AES-GCM cannot distinguish a wrong
+    /// key from damaged or tampered data, so the password is checked against
+    /// the sentinel: if it is the master password and the value was encrypted
+    /// with the master password, the value must be damaged (`DecryptFailed`).
+    /// Otherwise the password is presumed wrong (`BadPassword`).
+    fn unseal_err(&self, key: &str, custom: bool, password: &str, e: Error) -> Result<Error> {
+        if !matches!(e, Error::BadPassword) || custom {
+            return Ok(e);
+        }
+
+        match self.sentinel()? {
+            Some((val, nonce, kdf)) if self.opens_sentinel(&val, &nonce, kdf, password)? => {
+                Ok(Error::DecryptFailed(String::from(key)))
+            }
+            _ => Ok(e),
+        }
+    }
+
+    /// Re-encrypts the given decrypted bytes of the value associated with
+    /// the specified key using the given password and the current KDF,
+    /// unless the value has changed since it was read with the given nonce.
     /// Returns an error if unsuccessful.
-    fn init(&mut self) -> rusqlite::Result<usize> {
-        self.db.execute_batch(
-            "create table if not exists storage (
-                modified   int  default (strftime('%s', 'now')),
-                key        text unique not null,
-                val        text not null,
-                nonce      blob unique
-            );
-
-            create table if not exists salt (
-                data blob not null
-            );",
+    fn reseal(&self, key: &str, nonce: &[u8], txt: &[u8], password: &str) -> Result<()> {
+        let kdf = self.kdf();
+        let (c, n) = encrypt(
+            &mut **self.rng(),
+            &self.key(password.as_bytes(), &self.salt(), kdf),
+            txt,
+            key.as_bytes(),
+        )?;
+        self.conn()?.execute(
+            "update storage
+            set val = ?3, nonce = ?4, bound = 1, kdf = ?5, iterations = ?6
+            where store = ?7 and key = ?1 and nonce = ?2",
+            (
+                key,
+                nonce,
+                self.codec.encode(c),
+                n,
+                kdf.hash,
+                kdf.iterations,
+                &self.store,
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Returns whether the given password and associated data decrypt
+    /// the given stored value of the specified key or an error if the value
+    /// is malformed.
+    fn opens(
+        &self,
+        key: &str,
+        val: &str,
+        nonce: &[u8],
+        kdf: Kdf,
+        password: &str,
+        aad: &[u8],
+    ) -> Result<bool> {
+        match self.unseal(key, val, nonce, kdf, password, aad) {
+            Ok(_) => Ok(true),
+            Err(Error::BadPassword) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns whether the given password decrypts the given encrypted
+    /// sentinel to the expected text or an error if the sentinel is malformed.
+    /// The text is compared in constant time, like the authentication tag.
+    fn opens_sentinel(&self, val: &str, nonce: &[u8], kdf: Kdf, password: &str) -> Result<bool> {
+        match self.unseal(META_SENTINEL, val, nonce, kdf, password, b"") {
+            Ok(txt) => Ok(txt.as_slice().ct_eq(SENTINEL.as_bytes()).into()),
+            Err(Error::BadPassword) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns whether the given password is the master password, as with
+    /// `verify_password`, writing the sentinel with it if it is and none has
+    /// been written yet, or an error if the check or the write fails.
+    fn claim_master(&self, password: &str) -> Result<bool> {
+        let master = self.verify_password(password)?;
+        if master {
+            self.set_sentinel(password)?;
+        }
+        Ok(master)
+    }
+
+    /// Returns the encrypted password-verification sentinel, its nonce, and
+    /// its KDF, if one has been written, or an error if the lookup fails.
+    fn sentinel(&self) -> Result<Option<(String, Vec<u8>, Kdf)>> {
+        Ok(self
+            .conn()?
+            .prepare_cached(
+                "select val, nonce, kdf, iterations
+                from meta
+                where name = ?1",
+            )?
+            .query_row((META_SENTINEL,), |row| {
+                Ok((row.get(0)?, row.get(1)?, kdf_at(row, 2)?))
+            })
+            .optional()?)
+    }
+
+    /// Writes the password-verification sentinel encrypted with the given
+    /// password unless one already exists. Returns an error if unsuccessful.
+    fn set_sentinel(&self, password: &str) -> Result<()> {
+        if self.sentinel()?.is_some() {
+            return Ok(());
+        }
+
+        let (c, n) = encrypt(
+            &mut **self.rng(),
+            &self.key(password.as_bytes(), &self.salt(), self.kdf()),
+            SENTINEL.as_bytes(),
+            b"",
         )?;
+        self.conn()?.execute(
+            "insert into meta (name, val, nonce, kdf, iterations)
+            values (?1, ?2, ?3, ?4, ?5)
+            on conflict (name) do nothing",
+            (
+                META_SENTINEL,
+                self.codec.encode(c),
+                n,
+                self.opts.kdf_hash,
+                self.opts.kdf_iterations,
+            ),
+        )?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Depot {
+    /// Checkpoints the write-ahead log, if the database has one, into the
+    /// main database file, and wipes the salt, the master password the depot
+    /// was unlocked with, and every remembered key from memory. A pooled
+    /// depot only checkpoints if a connection is free, rather than wait for
+    /// one. Errors are ignored, since there is no one to return them to.
+    fn drop(&mut self) {
+        let checkpoint = "pragma wal_checkpoint(truncate)";
+        match &mut self.db {
+            Backend::Single(m) => {
+                let conn = m.get_mut().unwrap_or_else(|e| e.into_inner());
+                let _ = conn.query_row(checkpoint, (), |_| Ok(()));
+            }
+            #[cfg(feature = "pool")]
+            Backend::Pooled(p) => {
+                if let Some(conn) = p.try_get() {
+                    let _ = conn.query_row(checkpoint, (), |_| Ok(()));
+                }
+            }
+        }
+        self.lock();
+        self.salt
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .zeroize();
+    }
+}
+
+/// A value encrypted or encoded for storage, with how it was.
+struct Sealed {
+    data: String,
+    nonce: Option<Vec<u8>>,
+    custom: bool,
+    compressed: bool,
+    bound: bool,
+    kdf: Kdf,
+}
+
+/// A value as stored in the database, with what is needed to read it back.
+struct Stored {
+    val: String,
+    nonce: Option<Vec<u8>>,
+    custom: bool,
+    compressed: bool,
+    bound: bool,
+    kdf: Kdf,
+    expires: Option<i64>,
+}
+
+/// An entry read out of a depot with its value decrypted.
+struct Entry {
+    key: String,
+    val: Zeroizing<String>,
+    encrypted: bool,
+    modified: Option<i64>,
+}
+
+impl Stored {
+    /// Returns the stored value in the columns of the given row starting at
+    /// the given index: val, nonce, custom, compressed, bound, kdf,
+    /// iterations, and expires, in that order.
+    fn from_row(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<Stored> {
+        Ok(Stored {
+            val: row.get(idx)?,
+            nonce: row.get(idx + 1)?,
+            custom: row.get(idx + 2)?,
+            compressed: row.get(idx + 3)?,
+            bound: row.get(idx + 4)?,
+            kdf: kdf_at(row, idx + 5)?,
+            expires: row.get(idx + 7)?,
+        })
+    }
+}
+
+/// An iterator over the keys in a depot and their modified times.
+struct Entries<'a> {
+    depot: &'a Depot,
+    batch: std::vec::IntoIter<(String, Option<i64>)>,
+    last: Option<String>,
+    done: bool,
+}
+
+impl Entries<'_> {
+    /// Reads the next batch of keys or returns an error if unsuccessful.
+    fn load(&mut self) -> Result<()> {
+        let batch = self.depot.entries_after(self.last.as_deref())?;
+        self.done = batch.len() < ITER_BATCH;
+        if let Some((k, _)) = batch.last() {
+            self.last = Some(k.clone());
+        }
+        self.batch = batch.into_iter();
+        Ok(())
+    }
+}
+
+impl Iterator for Entries<'_> {
+    type Item = Result<(String, Option<i64>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.batch.next() {
+            return Some(Ok(e));
+        } else if self.done {
+            return None;
+        }
+
+        match self.load() {
+            Ok(()) => self.batch.next().map(Ok),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Migrates the depot in the given database whose tables carry the prefix in
+/// the given options to the current schema and returns its salt and encoding
+/// or an error if unsuccessful. If the depot is new, its salt is generated
+/// with the given random number generator, and its salt and encoding are as
+/// the options specify.
+fn init(
+    db: &mut rusqlite::Connection,
+    rng: &mut dyn RngCore,
+    opts: &Options,
+) -> rusqlite::Result<(Vec<u8>, Codec)> {
+    let prefix = &opts.table_prefix;
+    migrate::run(db, prefix)?;
+    let db = Prefixed { db, prefix };
+
+    let salt = match db
+        .query_row("select data from salt", (), |row| row.get(0))
+        .optional()?
+    {
+        Some(s) => s,
+        None => {
+            let mut salt = vec![0u8; opts.salt_len];
+            rng.fill_bytes(&mut salt);
+            db.execute("insert into salt (data) values (?1)", (&salt,))?;
+            if opts.url_safe_base64 {
+                db.execute(
+                    "insert into meta (name, val) values (?1, ?2)",
+                    (META_BASE64, BASE64_URL),
+                )?;
+            }
+            if opts.encoding == Encoding::Hex {
+                db.execute(
+                    "insert into meta (name, val) values (?1, ?2)",
+                    (META_ENCODING, ENCODING_HEX),
+                )?;
+            }
+            salt
+        }
+    };
+
+    let meta = |name: &str| -> rusqlite::Result<Option<String>> {
+        db.query_row("select val from meta where name = ?1", (name,), |row| {
+            row.get(0)
+        })
+        .optional()
+    };
+    let codec = match (
+        meta(META_ENCODING)?.as_deref(),
+        meta(META_BASE64)?.as_deref(),
+    ) {
+        (Some(ENCODING_HEX), _) => Codec::Hex,
+        (_, Some(BASE64_URL)) => Codec::Base64UrlSafe,
+        _ => Codec::Base64,
+    };
+    Ok((salt, codec))
+}
+
+/// Returns whether the given error is due to the database being busy or
+/// locked by another connection.
+fn is_busy(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+/// Returns whether the given database has a salt for the depot whose tables
+/// carry the given prefix, as every depot does, or an error if unsuccessful.
+fn has_salt(db: &rusqlite::Connection, prefix: &str) -> rusqlite::Result<bool> {
+    let db = Prefixed { db, prefix };
+    let table: bool = db.query_row(
+        "select count(*) > 0 from sqlite_master where type = 'table' and name = 'salt'",
+        (),
+        |row| row.get(0),
+    )?;
+    Ok(table && db.query_row("select count(*) > 0 from salt", (), |row| row.get(0))?)
+}
+
+/// Keeps the current value associated with the specified key in the given
+/// store, if there is one, as its newest version in the history. Returns an
+/// error if unsuccessful.
+fn snapshot(db: &Tx, store: &str, key: &str) -> Result<()> {
+    db.prepare_cached(
+        "insert into history
+            (store, key, version, modified, val, nonce, custom, compressed, bound, kdf, iterations)
+        select store, key,
+            (select coalesce(max(version), 0) + 1 from history where store = ?1 and key = ?2),
+            modified, val, nonce, custom, compressed, bound, kdf, iterations
+        from storage
+        where store = ?1 and key = ?2",
+    )?
+    .execute((store, key))?;
+    Ok(())
+}
+
+/// Returns a uniformly random index below n, which must not be zero, from
+/// the given random number generator. Draws from the top of its range that
+/// would make lower indices likelier are rejected and drawn again.
+fn uniform_index(rng: &mut dyn RngCore, n: usize) -> usize {
+    let n = n as u64;
+    let zone = u64::MAX - u64::MAX % n;
+    loop {
+        let x = rng.next_u64();
+        if x < zone {
+            return (x % n) as usize;
+        }
+    }
+}
+
+/// Records the row of the specified key in the given store as it is, or
+/// that there is none, as the state to which `undo` returns the key, in
+/// place of whatever was recorded for the store before. Returns an error if
+/// unsuccessful.
+fn journal(db: &Tx, store: &str, key: &str) -> Result<()> {
+    forget_journal(db, Some(store))?;
+    let saved = db
+        .prepare_cached(
+            "insert into journal
+                (store, key, modified, val, nonce, custom, compressed, bound,
+                iterations, kdf, expires, accessed, access_count, deleted, hint, tags)
+            select store, key, modified, val, nonce, custom, compressed, bound,
+                iterations, kdf, expires, accessed, access_count, deleted, hint, tags
+            from storage
+            where store = ?1 and key = ?2",
+        )?
+        .execute((store, key))?;
+    if saved == 0 {
+        db.prepare_cached("insert into journal (store, key) values (?1, ?2)")?
+            .execute((store, key))?;
+    }
+    Ok(())
+}
+
+/// Forgets what `undo` would undo in the given store, or in every store if
+/// None, e.g. because a later change cannot be undone. Returns an error if
+/// unsuccessful.
+fn forget_journal(db: &Tx, store: Option<&str>) -> Result<()> {
+    db.prepare_cached("delete from journal where ?1 is null or store = ?1")?
+        .execute((store,))?;
+    Ok(())
+}
+
+/// Deletes the oldest versions of the value associated with the specified
+/// key in the given store beyond the given limit from the history. Returns
+/// an error if unsuccessful.
+fn trim_history(db: &Tx, store: &str, key: &str, limit: usize) -> Result<()> {
+    db.prepare_cached(
+        "delete from history
+        where store = ?1 and key = ?2 and version <= (
+            select max(version) - ?3 from history where store = ?1 and key = ?2
+        )",
+    )?
+    .execute((store, key, limit))?;
+    Ok(())
+}
+
+/// Deletes every expired value in every store of the depot in the given
+/// database whose tables carry the given prefix. Returns the number of values
+/// deleted or an error if unsuccessful.
+fn prune_all(db: &rusqlite::Connection, prefix: &str) -> rusqlite::Result<usize> {
+    Prefixed { db, prefix }.execute("delete from storage where expires <= ?1", (now(),))
+}
+
+/// Returns the current time as a Unix timestamp.
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Returns the associated data an encrypted value stored under the given key
+/// is bound to: the key itself, or nothing for values that predate binding.
+fn aad(key: &str, bound: bool) -> &[u8] {
+    if bound {
+        key.as_bytes()
+    } else {
+        b""
+    }
+}
+
+/// Returns the set of tags in the given comma-separated list, if any.
+fn split_tags(tags: Option<&str>) -> BTreeSet<String> {
+    match tags {
+        Some(t) => t.split(',').map(String::from).collect(),
+        None => BTreeSet::new(),
+    }
+}
+
+/// Returns the error for a failed lookup of the given key,
+/// naming the key if it does not exist.
+fn lookup_err(key: &str, e: rusqlite::Error) -> Error {
+    match e {
+        rusqlite::Error::QueryReturnedNoRows => Error::NotFound(String::from(key)),
+        other => Error::from(other),
+    }
+}
 
-        rand::thread_rng().fill_bytes(&mut self.salt);
-        self.db
-            .execute("insert into salt (data) values (?1)", (&self.salt,))
+/// Returns the given data compressed, or None if compression would not
+/// make it any smaller, or an error if unsuccessful.
+fn compress(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut enc = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+    enc.write_all(data)?;
+    let out = enc.finish()?;
+    Ok(if out.len() < data.len() {
+        Some(out)
+    } else {
+        None
+    })
+}
+
+/// Returns the given compressed data decompressed or an error if unsuccessful.
+fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    flate2::read::DeflateDecoder::new(data).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+/// Returns the KDF recorded in the two columns of the given row starting at
+/// the given index, the hash followed by the iteration count.
+fn kdf_at(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<Kdf> {
+    Ok(Kdf {
+        hash: row.get(idx)?,
+        iterations: row.get(idx + 1)?,
+    })
+}
+
+/// Returns the encryption key derived from the given password and salt
+/// with the given KDF.
+/// The key is wiped from memory when it goes out of scope.
+fn derive_key(password: &[u8], salt: &[u8], kdf: Kdf) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    match kdf.hash {
+        KdfHash::Sha1 => pbkdf2_hmac::<Sha1>(password, salt, kdf.iterations, key.as_mut()),
+        KdfHash::Sha256 => pbkdf2_hmac::<Sha256>(password, salt, kdf.iterations, key.as_mut()),
     }
+    key
 }
 
-/// Returns the given data encrypted with a key derived from the given
-/// password and the nonce with which it was encrypted
-/// or an error if unsuccessful.
+/// The length of the nonce each value is encrypted with.
+const NONCE_LEN: usize = 12;
+
+/// Returns the given data encrypted with the given key and the nonce,
+/// drawn from the given random number generator, with which it was encrypted
+/// or an error if unsuccessful. The associated data (e.g. the key name)
+/// is authenticated but not encrypted, so decryption requires the same.
 fn encrypt(
-    password: &[u8],
-    salt: &[u8],
+    rng: &mut dyn RngCore,
+    key: &[u8; 32],
     data: &[u8],
+    aad: &[u8],
 ) -> std::result::Result<(Vec<u8>, Vec<u8>), aes_gcm::Error> {
-    let mut key = [0u8; 32];
-    pbkdf2_hmac::<Sha1>(password, salt, 4096, &mut key);
-
-    let cipher = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(&key));
-    let nonce = Aes256Gcm::generate_nonce(&mut aes_gcm::aead::OsRng);
-    let ciphertext = cipher.encrypt(&nonce, data)?;
+    let cipher = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(key.as_slice()));
+    let mut nonce = aes_gcm::Nonce::default();
+    rng.fill_bytes(&mut nonce);
+    let ciphertext = cipher.encrypt(&nonce, Payload { msg: data, aad })?;
 
     Ok((ciphertext, Vec::from(nonce.as_slice())))
 }
 
-/// Returns the given data decrypted with the key derived from the given
-/// password or an error if unsuccessful.
+/// Returns the given data decrypted with the given key or an error if
+/// unsuccessful, including if the associated data differs from that given
+/// at encryption or the nonce is not the length of a nonce.
 fn decrypt(
-    password: &[u8],
-    salt: &[u8],
+    key: &[u8; 32],
     nonce: &[u8],
     data: &[u8],
+    aad: &[u8],
 ) -> std::result::Result<Vec<u8>, aes_gcm::Error> {
-    let mut key = [0u8; 32];
-    pbkdf2_hmac::<Sha1>(password, salt, 4096, &mut key);
-
-    let cipher = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(&key));
+    if nonce.len() != NONCE_LEN {
+        return Err(aes_gcm::Error);
+    }
+    let cipher = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(key.as_slice()));
 
-    cipher.decrypt(aes_gcm::Nonce::from_slice(nonce), data)
+    cipher.decrypt(
+        aes_gcm::Nonce::from_slice(nonce),
+        Payload { msg: data, aad },
+    )
 }
 
 #[cfg(test)]
@@ -162,10 +3486,49 @@ mod tests {
         let password = "testpassword";
         let mut salt = [0u8; 32];
         rand::thread_rng().fill_bytes(&mut salt);
+        let kdf = Kdf {
+            hash: KdfHash::Sha256,
+            iterations: KDF_ITERATIONS,
+        };
 
-        let (ciphertext, nonce) = encrypt(password.as_bytes(), &salt, val.as_bytes()).unwrap();
-        let plaintext = decrypt(password.as_bytes(), &salt, &nonce, &ciphertext).unwrap();
+        let pw = password.as_bytes();
+        let key = derive_key(pw, &salt, kdf);
+        let (ciphertext, nonce) =
+            encrypt(&mut rand::thread_rng(), &key, val.as_bytes(), b"key").unwrap();
+        let plaintext = decrypt(&key, &nonce, &ciphertext, b"key").unwrap();
+        assert!(decrypt(&key, &nonce, &ciphertext, b"other").is_err());
+        let legacy = Kdf {
+            hash: KdfHash::Sha1,
+            ..kdf
+        };
+        let legacy_key = derive_key(pw, &salt, legacy);
+        assert!(decrypt(&legacy_key, &nonce, &ciphertext, b"key").is_err());
+        let fewer = Kdf {
+            iterations: 1,
+            ..kdf
+        };
+        let fewer_key = derive_key(pw, &salt, fewer);
+        assert!(decrypt(&fewer_key, &nonce, &ciphertext, b"key").is_err());
+        assert!(decrypt(&key, &nonce[..8], &ciphertext, b"key").is_err());
+        assert!(decrypt(
+            &key,
+            &[nonce.clone(), nonce.clone()].concat(),
+            &ciphertext,
+            b"key"
+        )
+        .is_err());
         assert_eq!(&plaintext, val.as_bytes());
         assert_eq!(String::from_utf8(plaintext).unwrap(), String::from(val));
     }
+
+    #[test]
+    fn test_uniform_index() {
+        // The first draw, u64::MAX, is past the last multiple of 3 and must
+        // be rejected; the next, 4, wraps around and is accepted.
+        let mut rng = rand::rngs::mock::StepRng::new(u64::MAX, 5);
+        assert_eq!(uniform_index(&mut rng, 3), 1);
+        let mut rng = rand::rngs::mock::StepRng::new(7, 1);
+        assert_eq!(uniform_index(&mut rng, 1), 0);
+        assert_eq!(uniform_index(&mut rng, 10), 8);
+    }
 }
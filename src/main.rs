@@ -2,83 +2,822 @@ use std::env;
 use std::fs;
 use std::io;
 use std::io::Write;
-use std::path::Path;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
 
+use subtle::ConstantTimeEq;
 use termion::input::TermRead;
+use zeroize::Zeroizing;
 
-use depot::{Depot, Error, Result};
+use depot::{Depot, Error, Options, Result, Strength};
 
 const ACT_STOW: &str = "stow";
 const ACT_FETCH: &str = "fetch";
 const ACT_DROP: &str = "drop";
+const ACT_GENERATE: &str = "generate";
+const ACT_BACKUP: &str = "backup";
+const ACT_OPTIMIZE: &str = "optimize";
+const ACT_CLEAR: &str = "clear";
+const ACT_PRUNE: &str = "prune";
+const ACT_UNDO: &str = "undo";
+const ACT_STATS: &str = "stats";
+const ACT_EDIT: &str = "edit";
+const ACT_LIST: &str = "list";
+const ACT_REPL: &str = "repl";
 const ACT_HELP: &str = "help";
+const ACT_VERSION: &str = "version";
+
+/// Every recognized action.
+const ACTIONS: &[&str] = &[
+    ACT_STOW,
+    ACT_FETCH,
+    ACT_DROP,
+    ACT_GENERATE,
+    ACT_BACKUP,
+    ACT_OPTIMIZE,
+    ACT_CLEAR,
+    ACT_PRUNE,
+    ACT_UNDO,
+    ACT_STATS,
+    ACT_EDIT,
+    ACT_LIST,
+    ACT_REPL,
+    ACT_HELP,
+    ACT_VERSION,
+];
+
+/// Actions that create the depot if it does not exist yet.
+const CREATING: &[&str] = &[ACT_STOW, ACT_GENERATE, ACT_EDIT, ACT_REPL];
+
+/// Actions that operate on the whole depot rather than a key.
+const KEYLESS: &[&str] = &[
+    ACT_OPTIMIZE,
+    ACT_CLEAR,
+    ACT_PRUNE,
+    ACT_UNDO,
+    ACT_STATS,
+    ACT_LIST,
+    ACT_REPL,
+];
+
+/// Actions that operate on the whole depot but take an optional glob pattern
+/// in place of a key, to operate on only the keys matching it.
+const PATTERNED: &[&str] = &[ACT_LIST];
+
+/// Actions that can report what they would remove instead of removing it.
+const PREVIEWABLE: &[&str] = &[ACT_DROP, ACT_CLEAR, ACT_PRUNE];
+
+/// The words that end a REPL session, as does the end of input.
+const REPL_EXIT: &[&str] = &["exit", "quit"];
 
 const ENV_PATH: &str = "DEPOT_PATH";
 const ENV_PASS: &str = "DEPOT_PASS";
+const ENV_PASS_FILE: &str = "DEPOT_PASS_FILE";
+
+const CONFIG_FILE: &str = "config";
+const CONFIG_PATH: &str = "path";
+const CONFIG_KDF_ITERATIONS: &str = "kdf_iterations";
+const CONFIG_CIPHER: &str = "cipher";
+
+/// The only supported cipher, which the config file may name explicitly.
+const CIPHER: &str = "aes-256-gcm";
+
+/// How long a value copied to the clipboard stays there before it is cleared.
+#[cfg(feature = "clipboard")]
+const CLIPBOARD_SECONDS: u64 = 45;
+
+const EXIT_FAILURE: i32 = 1;
+const EXIT_NOT_FOUND: i32 = 2;
+const EXIT_PASSWORD: i32 = 3;
+const EXIT_USAGE: i32 = 64;
+
+/// The defaults specified in the config file.
+#[derive(Default)]
+struct Config {
+    path: Option<String>,
+    kdf_iterations: Option<u32>,
+}
+
+/// The action, key, and options specified on the command line.
+struct Opts<'a> {
+    action: &'a str,
+    key: &'a str,
+    secret: bool,
+    newline: bool,
+    pass_file: Option<&'a str>,
+    file: Option<&'a str>,
+    output: Option<&'a str>,
+    clipboard: bool,
+    db_path: Option<&'a str>,
+    keep_newline: bool,
+    raw: bool,
+    multiline: bool,
+    length: usize,
+    charset: depot::Charset,
+    echo: bool,
+    json: bool,
+    quiet: bool,
+    yes: bool,
+    glob: bool,
+    dry_run: bool,
+    force: bool,
+    timeout: Option<Duration>,
+    null: bool,
+    count: bool,
+}
+
+/// How the actions of a REPL session share the master password so that it is
+/// only asked for once: the depot is unlocked with it. The depot is locked
+/// again, wiping the password from memory, when the session ends or when no
+/// action has been performed for as long as the session's timeout, if it has
+/// one. The depot is only unlocked if the session remembers passwords.
+#[derive(Default)]
+struct Session {
+    remember: bool,
+    timeout: Option<Duration>,
+    last: Option<Instant>,
+}
 
-fn main() -> Result<()> {
+impl Session {
+    /// Locks the given depot if the session has timed out since the last
+    /// action, saying so unless told to be quiet, and starts timing the
+    /// session afresh from now.
+    fn touch(&mut self, storage: &mut Depot, quiet: bool) {
+        let idle = self.last.map(|t| t.elapsed());
+        if let (Some(idle), Some(timeout)) = (idle, self.timeout) {
+            if idle >= timeout && storage.is_unlocked() {
+                storage.lock();
+                if !quiet {
+                    eprintln!(
+                        "Locked after {} seconds without an action",
+                        timeout.as_secs()
+                    );
+                }
+            }
+        }
+        self.last = Some(Instant::now());
+    }
+
+    /// Returns a password to encrypt a new value with, the master password
+    /// the given depot is unlocked with if it is, or else as with
+    /// `get_new_password`.
+    fn new_password(&self, storage: &Depot, pass_file: Option<&str>) -> Result<Zeroizing<String>> {
+        match storage.unlocked_password() {
+            Some(p) => Ok(Zeroizing::new(String::from(p))),
+            None => get_new_password(pass_file),
+        }
+    }
+
+    /// Unlocks the given depot with the given password, if the session
+    /// remembers passwords and the depot is not unlocked yet, provided that
+    /// it is the master password. Returns an error if the password could not
+    /// be checked.
+    fn remember(&mut self, storage: &mut Depot, password: &Zeroizing<String>) -> Result<()> {
+        if !self.remember || storage.is_unlocked() {
+            return Ok(());
+        }
+        match storage.unlock(password) {
+            Err(Error::BadPassword) => Ok(()),
+            r => r,
+        }
+    }
+}
+
+fn main() {
     let args: Vec<String> = env::args().collect();
-    let (action, key, secret, newline) = parse_args(&args[1..])?;
-    let db_path = choose_path()?;
-    let storage = Depot::new(&db_path)?;
+    let result = match parse_args(&args[1..]) {
+        Ok(opts) => run(&opts).map_err(|e| (exit_code(&e), e)),
+        Err(e) => Err((EXIT_USAGE, e)),
+    };
+
+    if let Err((code, e)) = result {
+        eprintln!("depot: {}", e);
+        process::exit(code);
+    }
+}
 
-    match action {
+/// Returns the exit status for the given error: distinct statuses for
+/// missing keys, missing or incorrect passwords, and invalid keys
+/// (as for other usage errors), and a general failure status otherwise.
+fn exit_code(e: &Error) -> i32 {
+    match e {
+        Error::NotFound(_) | Error::Expired(_) | Error::NoDepot(_) => EXIT_NOT_FOUND,
+        Error::NeedPassword | Error::BadPassword => EXIT_PASSWORD,
+        Error::InvalidKey(_) => EXIT_USAGE,
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Performs the action specified by the given options
+/// or returns an error if unsuccessful.
+fn run(opts: &Opts) -> Result<()> {
+    match opts.action {
+        ACT_HELP => {
+            println!("{}", usage());
+            return Ok(());
+        }
+        ACT_VERSION => {
+            println!("depot {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let config = read_config()?;
+    let db_path = choose_path(opts.db_path, &config)?;
+    let depot_opts = Options {
+        kdf_iterations: config.kdf_iterations.unwrap_or(depot::KDF_ITERATIONS),
+        dry_run: opts.dry_run,
+        ..Default::default()
+    };
+    // Only actions that store values create a depot, so that a mistyped path
+    // is reported rather than silently treated as a new, empty depot.
+    let mut storage = if CREATING.contains(&opts.action) {
+        Depot::with_options(&db_path, depot_opts)?
+    } else {
+        Depot::open_with_options(&db_path, depot_opts)?
+    };
+
+    if opts.action == ACT_REPL {
+        repl(&mut storage, opts.timeout)
+    } else {
+        perform(&mut storage, opts, &mut Session::default())
+    }
+}
+
+/// Reads actions with their keys and options from stdin, one per line as on
+/// the command line but without -d, and performs each on the given depot
+/// until the end of input or exit or quit, prompting for each when stdin is
+/// a terminal. The master password is asked for at most once, or once more
+/// each time the REPL is left idle for the given timeout. Returns an error
+/// only if stdin cannot be read, reporting the errors of each action as it
+/// goes.
+fn repl(storage: &mut Depot, timeout: Option<Duration>) -> Result<()> {
+    let mut session = Session {
+        remember: true,
+        timeout,
+        ..Session::default()
+    };
+    let tty = termion::is_tty(&io::stdin());
+
+    loop {
+        if tty {
+            print!("depot> ");
+            io::stdout().flush()?;
+        }
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let words: Vec<String> = line.split_whitespace().map(String::from).collect();
+        match words.first() {
+            None => continue,
+            Some(w) if REPL_EXIT.contains(&w.as_str()) => break,
+            Some(_) => {}
+        }
+
+        let result = parse_args(&words).and_then(|cmd| match cmd.action {
+            ACT_HELP | ACT_VERSION => run(&cmd),
+            ACT_REPL => Err(Error::from("already in the REPL")),
+            _ if cmd.db_path.is_some() => Err(Error::from("-d cannot be given in the REPL")),
+            _ if cmd.timeout.is_some() => Err(Error::from("-t cannot be given in the REPL")),
+            _ => {
+                session.touch(storage, cmd.quiet);
+                perform(storage, &cmd, &mut session)
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("depot: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Performs the action specified by the given options on the given depot,
+/// in the given session, or returns an error if unsuccessful.
+fn perform(storage: &mut Depot, opts: &Opts, session: &mut Session) -> Result<()> {
+    let key = opts.key;
+    match opts.action {
         ACT_STOW => {
-            let val = get_val(secret)?;
-            let password = if secret { Some(get_password()?) } else { None };
-            storage.stow(key, &val, password.as_deref())
+            // Refuse before asking for a value that could not be stowed.
+            if !opts.force && storage.metadata(key).is_ok() {
+                return Err(Error::AlreadyExists(String::from(key)));
+            }
+            let val = match opts.file {
+                Some(f) => read_val(f, opts.keep_newline || opts.raw)?,
+                None => get_val(opts.secret, opts.raw, opts.multiline)?,
+            };
+            let password = if opts.secret {
+                Some(session.new_password(storage, opts.pass_file)?)
+            } else {
+                None
+            };
+            if let Some(p) = &password {
+                warn_if_weak(p, opts.quiet);
+            }
+            let pw = password.as_ref().map(|p| p.as_str());
+            if opts.force {
+                storage.stow(key, &val, pw)?;
+            } else {
+                storage.stow_new(key, &val, pw)?;
+            }
+            match password {
+                Some(p) => session.remember(storage, &p),
+                None => Ok(()),
+            }
         }
         ACT_FETCH => {
             let val = match storage.fetch(key, None) {
                 Ok(v) => v,
-                Err(Error::NeedPassword) => storage.fetch(key, Some(&get_password()?))?,
+                Err(Error::NeedPassword) => {
+                    let password = get_fetch_password(storage, key, opts.pass_file)?;
+                    let val = storage.fetch(key, Some(&password))?;
+                    session.remember(storage, &password)?;
+                    val
+                }
+                Err(e) => return Err(e),
+            };
+
+            if let Some(path) = opts.output {
+                write_val(path, &val)?;
+            } else if opts.clipboard {
+                copy_val(&val, opts.quiet)?;
+            } else if opts.json {
+                println!(r#"{{"key":{},"value":{}}}"#, json_str(key), json_str(&val));
+            } else {
+                print!("{}{}", val, if opts.newline { "\n" } else { "" });
+            }
+            Ok(())
+        }
+        ACT_EDIT => {
+            let (val, password) = match storage.fetch(key, None) {
+                Ok(v) => (v, None),
+                Err(Error::NeedPassword) => {
+                    let password = get_fetch_password(storage, key, opts.pass_file)?;
+                    (storage.fetch(key, Some(&password))?, Some(password))
+                }
+                Err(Error::NotFound(_) | Error::Expired(_)) if opts.secret => (
+                    String::new(),
+                    Some(session.new_password(storage, opts.pass_file)?),
+                ),
+                Err(Error::NotFound(_) | Error::Expired(_)) => (String::new(), None),
                 Err(e) => return Err(e),
             };
 
-            print!("{}{}", val, if newline { "\n" } else { "" });
+            let val = edit_val(&val, password.is_some(), opts.raw, opts.quiet)?;
+            storage.stow(key, &val, password.as_ref().map(|p| p.as_str()))?;
+            match password {
+                Some(p) => session.remember(storage, &p),
+                None => Ok(()),
+            }
+        }
+        ACT_DROP if opts.glob && opts.dry_run => {
+            let keys = storage.keys_matching(key)?;
+            report_dry_run(&keys, opts.quiet);
+            Ok(())
+        }
+        ACT_DROP if opts.glob => {
+            let question = format!("Delete every key matching '{}'? [y/N] ", key);
+            if confirm(&question, opts.yes)? {
+                let removed = storage.drop_matching(key)?;
+                if !opts.quiet {
+                    eprintln!("Removed {} keys", removed);
+                }
+            }
+            Ok(())
+        }
+        ACT_DROP if opts.dry_run => {
+            if Depot::drop(storage, key)? {
+                report_dry_run(&[key], opts.quiet);
+            } else if !opts.quiet {
+                eprintln!("depot: nothing to drop: {} is not in the depot", key);
+            }
+            Ok(())
+        }
+        ACT_DROP => {
+            if !confirm(&format!("Delete '{}'? [y/N] ", key), opts.yes)? {
+                return Ok(());
+            }
+            if !Depot::drop(storage, key)? && !opts.quiet {
+                eprintln!("depot: nothing to drop: {} is not in the depot", key);
+            }
+            Ok(())
+        }
+        ACT_GENERATE => {
+            let password = session.new_password(storage, opts.pass_file)?;
+            warn_if_weak(&password, opts.quiet);
+            let gen_opts = depot::GenOptions {
+                length: opts.length,
+                charset: opts.charset.clone(),
+            };
+            let val = storage.generate(key, gen_opts, Some(&password))?;
+            session.remember(storage, &password)?;
+            if opts.echo {
+                print!("{}{}", *val, if opts.newline { "\n" } else { "" });
+            }
+            Ok(())
+        }
+        ACT_BACKUP => storage.backup(key),
+        ACT_OPTIMIZE => storage.compact(),
+        ACT_CLEAR if opts.dry_run => {
+            report_dry_run(&storage.keys()?, opts.quiet);
+            Ok(())
+        }
+        ACT_CLEAR => {
+            if confirm("Delete every key in the depot? [y/N] ", opts.yes)? {
+                let removed = storage.clear()?;
+                if !opts.quiet {
+                    eprintln!("Removed {} keys", removed);
+                }
+            }
+            Ok(())
+        }
+        ACT_PRUNE if opts.dry_run => {
+            report_dry_run(&storage.expired_keys()?, opts.quiet);
+            Ok(())
+        }
+        ACT_PRUNE => {
+            let removed = storage.prune_expired()?;
+            if !opts.quiet {
+                eprintln!("Removed {} expired keys", removed);
+            }
+            Ok(())
+        }
+        ACT_UNDO => match storage.undo()? {
+            Some(key) => {
+                if !opts.quiet {
+                    eprintln!("Undid the last change to {}", key);
+                }
+                Ok(())
+            }
+            None => Err(Error::from("there is nothing to undo")),
+        },
+        ACT_LIST if opts.count => {
+            let pattern = Some(key).filter(|k| !k.is_empty());
+            println!("{}", storage.count(pattern)?);
+            Ok(())
+        }
+        ACT_LIST => {
+            let keys = match key {
+                "" => storage.keys()?,
+                pattern => storage.keys_matching(pattern)?,
+            };
+            if opts.json {
+                let keys: Vec<String> = keys.iter().map(|k| json_str(k)).collect();
+                println!("[{}]", keys.join(","));
+            } else if opts.null {
+                let mut out = io::stdout().lock();
+                for k in keys {
+                    write!(out, "{}\0", k)?;
+                }
+                out.flush()?;
+            } else {
+                for k in keys {
+                    println!("{}", k);
+                }
+            }
+            Ok(())
+        }
+        ACT_STATS if opts.json => {
+            let stats = storage.stats()?;
+            let time = |t: Option<i64>| t.map_or(String::from("null"), |t| t.to_string());
+            println!(
+                r#"{{"total":{},"encrypted":{},"plaintext":{},"earliest":{},"latest":{},"file_size":{}}}"#,
+                stats.total,
+                stats.encrypted,
+                stats.plaintext,
+                time(stats.earliest),
+                time(stats.latest),
+                stats.file_size
+            );
+            Ok(())
+        }
+        ACT_STATS => {
+            let stats = storage.stats()?;
+            println!("Keys:           {}", stats.total);
+            println!("  Encrypted:    {}", stats.encrypted);
+            println!("  Plaintext:    {}", stats.plaintext);
+            if let (Some(earliest), Some(latest)) = (stats.earliest, stats.latest) {
+                println!("Least recent:   {}", format_time(earliest));
+                println!("Most recent:    {}", format_time(latest));
+            }
+            println!("File size:      {} bytes", stats.file_size);
             Ok(())
         }
-        ACT_DROP => storage.drop(key),
-        ACT_HELP => Ok(println!("{}", usage())),
         act => Err(Error::from(format!("unrecognized action: {}", act))),
     }
 }
 
-/// Returns the password from either an environment variable or console input
-/// or an error if unsuccessful.
-fn get_password() -> Result<String> {
-    match env::var(ENV_PASS) {
-        Ok(p) => Ok(p),
-        _ => {
-            let mut tty_in = fs::File::open("/dev/tty")?;
-            let mut tty_out = fs::File::create("/dev/tty")?;
-            tty_out.write_all("PASSWORD: ".as_bytes())?;
+/// Warns that the given password is weak, as `depot::password_strength`
+/// estimates, unless quiet. The warning is only advice; nothing is refused.
+fn warn_if_weak(password: &str, quiet: bool) {
+    if !quiet && depot::password_strength(password) == Strength::Weak {
+        eprintln!("warning: the password is weak; a longer one mixing letters, digits, and symbols is harder to guess");
+    }
+}
 
-            let password = tty_in.read_passwd(&mut tty_out)?;
-            tty_out.write_all("\n".as_bytes())?;
+/// Returns the password from the given file, the file named by an
+/// environment variable, another environment variable, or console input,
+/// in that order of precedence, or an error if unsuccessful. The console
+/// prompt is the given text. The password is wiped from memory when dropped.
+fn get_password(prompt: &str, pass_file: Option<&str>) -> Result<Zeroizing<String>> {
+    match supplied_password(pass_file)? {
+        Some(p) => Ok(p),
+        None => prompt_password(prompt),
+    }
+}
 
-            match password {
-                Some(p) => Ok(String::from(p.trim())),
-                None => Err(Error::BadPassword),
+/// Returns the password to decrypt the value associated with the given key,
+/// as with `get_password`, prompting for the master password or that key's
+/// custom password as appropriate, after the value's password hint, if any.
+fn get_fetch_password(
+    storage: &Depot,
+    key: &str,
+    pass_file: Option<&str>,
+) -> Result<Zeroizing<String>> {
+    let prompt = if storage.has_custom_password(key)? {
+        format!("PASSWORD FOR {}: ", key)
+    } else {
+        String::from("MASTER PASSWORD: ")
+    };
+    let prompt = match storage.hint(key)? {
+        Some(hint) => format!("HINT: {}\n{}", hint, prompt),
+        None => prompt,
+    };
+    get_password(&prompt, pass_file)
+}
+
+/// Returns a password to encrypt a new value with, as with `get_password`,
+/// except that a password entered on the console must be entered twice.
+/// Returns an error if the two entries do not match.
+fn get_new_password(pass_file: Option<&str>) -> Result<Zeroizing<String>> {
+    match supplied_password(pass_file)? {
+        Some(p) => Ok(p),
+        None => {
+            let password = prompt_password("PASSWORD: ")?;
+            let confirmed = prompt_password("CONFIRM PASSWORD: ")?;
+            if !bool::from(confirmed.as_bytes().ct_eq(password.as_bytes())) {
+                return Err(Error::from("passwords do not match"));
             }
+            Ok(password)
         }
     }
 }
 
-/// Returns the value read from stdin or an error if unsuccessful
-fn get_val(secret: bool) -> Result<String> {
-    let val = if secret && termion::is_tty(&io::stdin()) {
+/// Returns the password from the given file, the file named by an
+/// environment variable, or another environment variable, if any,
+/// or an error if a password file cannot be read.
+fn supplied_password(pass_file: Option<&str>) -> Result<Option<Zeroizing<String>>> {
+    let file = match pass_file {
+        Some(f) => Some(String::from(f)),
+        None => env::var(ENV_PASS_FILE).ok(),
+    };
+
+    if let Some(f) = file {
+        let contents = Zeroizing::new(fs::read_to_string(f)?);
+        return match contents.trim_end_matches(['\r', '\n']) {
+            "" => Err(Error::from("password file is empty")),
+            p => Ok(Some(Zeroizing::new(String::from(p)))),
+        };
+    }
+
+    Ok(env::var(ENV_PASS).ok().map(Zeroizing::new))
+}
+
+/// Returns the password entered on the console after displaying
+/// the given prompt or an error if unsuccessful.
+fn prompt_password(prompt: &str) -> Result<Zeroizing<String>> {
+    let mut tty_in = fs::File::open("/dev/tty")?;
+    let mut tty_out = fs::File::create("/dev/tty")?;
+    tty_out.write_all(prompt.as_bytes())?;
+
+    let password = tty_in.read_passwd(&mut tty_out)?.map(Zeroizing::new);
+    tty_out.write_all("\n".as_bytes())?;
+
+    match password {
+        Some(p) => Ok(Zeroizing::new(String::from(p.trim()))),
+        None => Err(Error::BadPassword),
+    }
+}
+
+/// Returns whether the user answers yes to the given question on the console,
+/// or true without asking if the user already said yes on the command line,
+/// or an error if unsuccessful. Since a script cannot answer, it is an error
+/// to ask when stdin is not a terminal.
+fn confirm(question: &str, yes: bool) -> Result<bool> {
+    if yes {
+        return Ok(true);
+    } else if !termion::is_tty(&io::stdin()) {
+        return Err(Error::from("confirmation required: use -y"));
+    }
+
+    let mut tty_in = fs::File::open("/dev/tty")?;
+    let mut tty_out = fs::File::create("/dev/tty")?;
+    tty_out.write_all(question.as_bytes())?;
+
+    match tty_in.read_line()? {
+        Some(a) => Ok(a.trim().eq_ignore_ascii_case("y") || a.trim().eq_ignore_ascii_case("yes")),
+        None => Ok(false),
+    }
+}
+
+/// Returns the value read from stdin or an error if unsuccessful.
+/// A raw value is all of stdin, stored exactly as read, and a multiline
+/// value is all of stdin with surrounding whitespace removed, rather than
+/// its first line with surrounding whitespace removed. A secret value typed
+/// on the terminal is read without echo, until end of input if multiline,
+/// and is otherwise the same as if it were piped in, as with `read_input`.
+fn get_val(secret: bool, raw: bool, multiline: bool) -> Result<String> {
+    if !(secret && termion::is_tty(&io::stdin())) {
+        return read_input(&mut io::stdin().lock(), secret, raw, multiline);
+    }
+
+    let val = if multiline {
+        let mut lines = Vec::new();
+        while let Some(line) = io::stdin().read_passwd(&mut io::stdout())? {
+            lines.push(Zeroizing::new(line));
+        }
+        lines
+            .iter()
+            .map(|l| l.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
         match io::stdin().read_passwd(&mut io::stdout())? {
             Some(v) => v,
             None => return Err(Error::from("value must be a non-empty string")),
         }
+    };
+
+    check_val(&val, raw)
+}
+
+/// Returns the value read from the given input, which is not a terminal, as
+/// with `get_val`, or an error if unsuccessful. Since a secret typed on the
+/// terminal ends at the newline that enters it, a raw secret read here is
+/// stored without its final line ending, so that piping a secret in stores
+/// the same value as typing it.
+fn read_input(
+    input: &mut impl io::BufRead,
+    secret: bool,
+    raw: bool,
+    multiline: bool,
+) -> Result<String> {
+    let mut val = String::new();
+    if raw || multiline {
+        input.read_to_string(&mut val)?;
+    } else {
+        input.read_line(&mut val)?;
+    }
+
+    if secret && raw {
+        check_val(strip_newline(&val), raw)
+    } else {
+        check_val(&val, raw)
+    }
+}
+
+/// Returns the given text less one trailing line ending, if it has one.
+fn strip_newline(text: &str) -> &str {
+    text.strip_suffix("\r\n")
+        .or_else(|| text.strip_suffix('\n'))
+        .unwrap_or(text)
+}
+
+/// Returns the entire contents of the given file, less a trailing newline
+/// unless it is to be kept, or an error if unsuccessful or the file is empty.
+fn read_val(path: &str, keep_newline: bool) -> Result<String> {
+    let contents = fs::read_to_string(path)?;
+    let val = if keep_newline {
+        &contents
     } else {
-        let mut v = String::new();
-        io::stdin().read_line(&mut v)?;
-        v
+        strip_newline(&contents)
     };
 
-    match val.trim() {
+    match val {
+        "" => Err(Error::from("value must be a non-empty string")),
+        v => Ok(String::from(v)),
+    }
+}
+
+/// Prints the given keys, which a dry run found would be removed, one per
+/// line, and how many there are unless quiet.
+fn report_dry_run<K: AsRef<str>>(keys: &[K], quiet: bool) {
+    for k in keys {
+        println!("{}", k.as_ref());
+    }
+    if !quiet {
+        eprintln!("Would remove {} keys", keys.len());
+    }
+}
+
+/// Writes the given value, exactly, to the given file, which is created
+/// readable only by the user if it does not exist and is otherwise replaced.
+/// Returns an error if unsuccessful.
+fn write_val(path: &str, val: &str) -> Result<()> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(val.as_bytes())?;
+    Ok(())
+}
+
+/// Copies the given value to the clipboard and, after `CLIPBOARD_SECONDS`,
+/// clears the clipboard unless something else has been copied since,
+/// reporting as much unless quiet. The process keeps running until then,
+/// which on X11 is what keeps the value available to paste. Returns an error
+/// if the clipboard is unavailable.
+#[cfg(feature = "clipboard")]
+fn copy_val(val: &str, quiet: bool) -> Result<()> {
+    let clipboard_err = |e: arboard::Error| Error::from(format!("clipboard: {}", e));
+    let mut clipboard = arboard::Clipboard::new().map_err(clipboard_err)?;
+    clipboard.set_text(val).map_err(clipboard_err)?;
+    if !quiet {
+        eprintln!(
+            "Copied to the clipboard; clearing in {} seconds",
+            CLIPBOARD_SECONDS
+        );
+    }
+
+    std::thread::sleep(std::time::Duration::from_secs(CLIPBOARD_SECONDS));
+    match clipboard.get_text().map(Zeroizing::new) {
+        Ok(text) if text.as_str() == val => clipboard.clear().map_err(clipboard_err),
+        _ => Ok(()),
+    }
+}
+
+/// Returns an error, since copying to the clipboard requires the
+/// `clipboard` feature.
+#[cfg(not(feature = "clipboard"))]
+fn copy_val(_: &str, _: bool) -> Result<()> {
+    Err(Error::from(
+        "-c requires depot to be built with the clipboard feature",
+    ))
+}
+
+/// Returns the given value after editing it in the user's editor or an error
+/// if unsuccessful. The value is written to a temporary file readable only by
+/// the user, which is kept in memory (/dev/shm) for secret values if possible,
+/// and which is deleted afterward. Warns otherwise unless quiet.
+fn edit_val(val: &str, secret: bool, raw: bool, quiet: bool) -> Result<String> {
+    let shm = Path::new("/dev/shm");
+    let dir = if secret && shm.is_dir() {
+        PathBuf::from(shm)
+    } else {
+        env::temp_dir()
+    };
+    if secret && dir != shm && !quiet {
+        eprintln!(
+            "warning: the decrypted value will be written to {} while editing",
+            dir.display()
+        );
+    }
+
+    let path = dir.join(format!("depot-{:016x}", rand::random::<u64>()));
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?
+        .write_all(val.as_bytes())?;
+
+    let edited = run_editor(&path).and_then(|_| Ok(fs::read_to_string(&path)?));
+    fs::remove_file(&path)?;
+    check_val(&edited?, raw)
+}
+
+/// Opens the given file in the editor named by $VISUAL or $EDITOR
+/// (or vi if neither is set) and waits for it to exit.
+/// Returns an error if the editor cannot be run or fails.
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let mut words = editor.split_whitespace();
+    let cmd = match words.next() {
+        Some(c) => c,
+        None => return Err(Error::from("no editor specified")),
+    };
+
+    let status = process::Command::new(cmd).args(words).arg(path).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::from(format!("editor exited with {}", status)))
+    }
+}
+
+/// Returns the given value with surrounding whitespace removed, unless it
+/// is raw, or an error if nothing remains.
+fn check_val(val: &str, raw: bool) -> Result<String> {
+    match if raw { val } else { val.trim() } {
         "" => Err(Error::from("value must be a non-empty string")),
         v => Ok(String::from(v)),
     }
@@ -86,84 +825,546 @@ fn get_val(secret: bool) -> Result<String> {
 
 /// Returns the key, options, and action to perform specified in
 /// the command-line arguments or an error if parsing is unsuccessful.
-fn parse_args(args: &[String]) -> Result<(&str, &str, bool, bool)> {
-    let mut action = "";
-    let mut key = "";
-    let mut secret = false;
-    let mut newline = true;
+fn parse_args(args: &[String]) -> Result<Opts<'_>> {
+    let mut opts = Opts {
+        action: "",
+        key: "",
+        secret: false,
+        newline: true,
+        pass_file: None,
+        file: None,
+        output: None,
+        clipboard: false,
+        db_path: None,
+        keep_newline: false,
+        raw: false,
+        multiline: false,
+        length: 20,
+        charset: depot::Charset::Printable,
+        echo: false,
+        json: false,
+        quiet: false,
+        yes: false,
+        glob: false,
+        dry_run: false,
+        force: false,
+        timeout: None,
+        null: false,
+        count: false,
+    };
+    let mut iter = args.iter();
 
-    for a in args.iter() {
+    while let Some(a) = iter.next() {
         if a == "-h" || a == "--help" || a == "-?" {
-            return Ok((ACT_HELP, key, secret, newline));
+            opts.action = ACT_HELP;
+            return Ok(opts);
+        } else if a == "-V" || a == "--version" {
+            opts.action = ACT_VERSION;
+            return Ok(opts);
         }
 
-        if a.starts_with('-') {
-            secret = secret || a.contains('s');
-            newline = newline && !a.contains('n');
-        } else if action.is_empty() {
-            if a == ACT_HELP {
-                return Ok((ACT_HELP, key, secret, newline));
+        if a == "--password-file" {
+            opts.pass_file = Some(flag_val(a, iter.next())?);
+        } else if a == "--database" {
+            opts.db_path = Some(flag_val(a, iter.next())?);
+        } else if a == "--output" {
+            opts.output = Some(flag_val(a, iter.next())?);
+        } else if a == "--keep-newline" {
+            opts.keep_newline = true;
+        } else if a == "--raw" {
+            opts.raw = true;
+        } else if a == "--multiline" {
+            opts.multiline = true;
+        } else if a == "--clipboard" {
+            opts.clipboard = true;
+        } else if a == "--json" {
+            opts.json = true;
+        } else if a == "--quiet" {
+            opts.quiet = true;
+        } else if a == "--yes" {
+            opts.yes = true;
+        } else if a == "--glob" {
+            opts.glob = true;
+        } else if a == "--dry-run" {
+            opts.dry_run = true;
+        } else if a == "--force" {
+            opts.force = true;
+        } else if a == "--count" {
+            opts.count = true;
+        } else if a == "--null" {
+            opts.null = true;
+        } else if a == "--length" {
+            opts.length = parse_length(flag_val(a, iter.next())?)?;
+        } else if a == "--charset" {
+            opts.charset = parse_charset(flag_val(a, iter.next())?);
+        } else if a == "--timeout" {
+            opts.timeout = Some(parse_timeout(flag_val(a, iter.next())?)?);
+        } else if a == "--file" {
+            // -f is the file stow reads, so the database path is -d.
+            return Err(Error::from(
+                "unrecognized option: --file (use -d or --database for the database path)",
+            ));
+        } else if a.starts_with("--") {
+            return Err(Error::from(format!("unrecognized option: {}", a)));
+        } else if let Some(flags) = a.strip_prefix('-') {
+            if flags.is_empty() {
+                return Err(Error::from("unrecognized option: -"));
+            }
+            // Each flag is read in the order typed, and each that takes a
+            // value takes the next argument, e.g. -pf <password-file> <file>.
+            for c in flags.chars() {
+                match c {
+                    'h' | '?' => {
+                        opts.action = ACT_HELP;
+                        return Ok(opts);
+                    }
+                    'V' => {
+                        opts.action = ACT_VERSION;
+                        return Ok(opts);
+                    }
+                    's' => opts.secret = true,
+                    'n' => opts.newline = false,
+                    'e' => opts.echo = true,
+                    'r' => opts.raw = true,
+                    'm' => opts.multiline = true,
+                    'q' => opts.quiet = true,
+                    'y' => opts.yes = true,
+                    'g' => opts.glob = true,
+                    'c' => opts.clipboard = true,
+                    '0' => opts.null = true,
+                    'a' => opts.charset = depot::Charset::Alphanumeric,
+                    'p' => opts.pass_file = Some(flag_val("-p", iter.next())?),
+                    'f' => opts.file = Some(flag_val("-f", iter.next())?),
+                    'o' => opts.output = Some(flag_val("-o", iter.next())?),
+                    'd' => opts.db_path = Some(flag_val("-d", iter.next())?),
+                    'l' => opts.length = parse_length(flag_val("-l", iter.next())?)?,
+                    't' => opts.timeout = Some(parse_timeout(flag_val("-t", iter.next())?)?),
+                    _ => return Err(Error::from(format!("unrecognized option: -{}", c))),
+                }
+            }
+        } else if opts.action.is_empty() {
+            if !ACTIONS.contains(&a.as_str()) {
+                return Err(Error::from(format!("unrecognized action: {}", a)));
             }
-            action = a;
-        } else if key.is_empty() {
-            key = a;
+            if a == ACT_HELP || a == ACT_VERSION {
+                opts.action = a;
+                return Ok(opts);
+            }
+            opts.action = a;
+        } else if opts.key.is_empty()
+            && (!KEYLESS.contains(&opts.action) || PATTERNED.contains(&opts.action))
+        {
+            opts.key = a;
         } else {
             return Err(Error::from("one key at a time"));
         }
     }
 
-    if action.is_empty() {
+    if opts.action.is_empty() {
         Err(Error::from("no action specified"))
-    } else if key.is_empty() {
+    } else if opts.key.is_empty() && !KEYLESS.contains(&opts.action) {
         Err(Error::from("no key specified"))
+    } else if opts.dry_run && !PREVIEWABLE.contains(&opts.action) {
+        Err(Error::from(format!(
+            "{} does not support --dry-run",
+            opts.action
+        )))
     } else {
-        Ok((action, key, secret, newline))
-    }
-}
-
-/// Returns the location of the database in the filesystem
-/// depending on the environment or an error if a path cannot be determined.
-fn choose_path() -> Result<String> {
-    match env::var(ENV_PATH) {
-        Ok(p) => Ok(p),
-        _ => {
-            let path = match env::var("XDG_CONFIG_HOME") {
-                Ok(p) => Path::new(&p).join("depot"),
-                _ => match env::var("HOME") {
-                    Ok(p) => Path::new(&p).join(".depot"),
-                    _ => Path::new(".").join(".depot"),
-                },
-            };
+        Ok(opts)
+    }
+}
 
-            fs::create_dir_all(&path)?;
-            match path.join("depot.db").to_str() {
-                None => Err(Error::from("config path has bad characters")),
-                Some(p) => Ok(String::from(p)),
-            }
+/// Returns the value following the given command-line flag
+/// or an error if there is none.
+fn flag_val<'a>(flag: &str, val: Option<&'a String>) -> Result<&'a str> {
+    match val {
+        Some(v) => Ok(v),
+        None => Err(Error::from(format!("{} requires a value", flag))),
+    }
+}
+
+/// Returns the given length of a generated value or an error if it is not a
+/// number.
+fn parse_length(len: &str) -> Result<usize> {
+    len.parse()
+        .map_err(|_| Error::from("length must be a number"))
+}
+
+/// Returns the character set with the given name, digits, alphanumeric, or
+/// printable, or else the set of the given characters themselves.
+fn parse_charset(set: &str) -> depot::Charset {
+    match set {
+        "digits" => depot::Charset::Digits,
+        "alphanumeric" => depot::Charset::Alphanumeric,
+        "printable" => depot::Charset::Printable,
+        chars => depot::Charset::Custom(String::from(chars)),
+    }
+}
+
+/// Returns the given REPL timeout, in seconds, or an error if it is not a
+/// positive whole number.
+fn parse_timeout(secs: &str) -> Result<Duration> {
+    match secs.parse() {
+        Ok(n) if n > 0 => Ok(Duration::from_secs(n)),
+        _ => Err(Error::from("timeout must be a positive number of seconds")),
+    }
+}
+
+/// Returns the location of the database in the filesystem: the given path,
+/// if any, or else depending on the environment and config file, in that
+/// order of precedence, or an error if a path cannot be determined.
+fn choose_path(path: Option<&str>, config: &Config) -> Result<String> {
+    if let Some(p) = path {
+        return Ok(String::from(p));
+    } else if let Ok(p) = env::var(ENV_PATH) {
+        return Ok(p);
+    } else if let Some(p) = &config.path {
+        return Ok(p.clone());
+    }
+
+    let dir = config_dir();
+    fs::create_dir_all(&dir)?;
+    match dir.join("depot.db").to_str() {
+        None => Err(Error::from("config path has bad characters")),
+        Some(p) => Ok(String::from(p)),
+    }
+}
+
+/// Returns the directory holding the default database and config file.
+fn config_dir() -> PathBuf {
+    match env::var("XDG_CONFIG_HOME") {
+        Ok(p) => Path::new(&p).join("depot"),
+        _ => match env::var("HOME") {
+            Ok(p) => Path::new(&p).join(".depot"),
+            _ => Path::new(".").join(".depot"),
+        },
+    }
+}
+
+/// Returns the defaults in the config file, if there is one,
+/// or an error if it cannot be read or parsed.
+fn read_config() -> Result<Config> {
+    match fs::read_to_string(config_dir().join(CONFIG_FILE)) {
+        Ok(text) => parse_config(&text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+/// Returns the defaults in the given config file text or an error if it is
+/// malformed. The file holds `name = value` lines, where a value may be
+/// quoted, and blank lines and lines starting with # are ignored.
+fn parse_config(text: &str) -> Result<Config> {
+    let mut config = Config::default();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let bad_line = || Error::from(format!("{} line {} is malformed", CONFIG_FILE, i + 1));
+        let (name, val) = line.split_once('=').ok_or_else(bad_line)?;
+        let val = val.trim();
+        let val = match val.strip_prefix('"') {
+            Some(v) => v.strip_suffix('"').ok_or_else(bad_line)?,
+            None => val,
+        };
+
+        match name.trim() {
+            CONFIG_PATH => config.path = Some(String::from(val)),
+            CONFIG_KDF_ITERATIONS => match val.parse() {
+                Ok(n) => config.kdf_iterations = Some(n),
+                Err(_) => return Err(Error::from("kdf_iterations must be a number")),
+            },
+            CONFIG_CIPHER if val.eq_ignore_ascii_case(CIPHER) => {}
+            CONFIG_CIPHER => return Err(Error::from(format!("unsupported cipher: {}", val))),
+            n => return Err(Error::from(format!("unrecognized config setting: {}", n))),
         }
     }
+
+    Ok(config)
+}
+
+/// Returns the given text as a quoted and escaped JSON string.
+fn json_str(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Returns the given Unix timestamp formatted as a UTC date and time.
+fn format_time(ts: i64) -> String {
+    // Converts days since the epoch to a civil date as described in
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let (days, secs) = (ts.div_euclid(86400), ts.rem_euclid(86400));
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
 }
 
 /// Returns the help message
 fn usage() -> String {
     [
-        "Usage: depot [-nsh?] <action> <key>",
+        "Usage: depot [-nsrmaeqygc0hV?] [-p <file>] [-f <file>] [-o <file>] [-d <path>] [-l <length>] [-t <secs>] <action> [<key>]",
         "",
         "Actions:",
         "    stow        Read a value from stdin and associate it with the given key",
         "    fetch       Print the value associated with the given key to stdout",
-        "    drop        Remove the given key from the depot",
+        "    drop        Remove the given key from the depot after confirmation",
+        "    generate    Generate a random value and stow it encrypted with the given key",
+        "    backup      Copy the entire depot to a new database at the given path",
+        "                (given in place of a key)",
+        "    optimize    Reclaim unused space in the depot's database file",
+        "    clear       Remove every key from the depot after confirmation",
+        "    prune       Remove every expired key from the depot",
+        "    undo        Revert the last stow or drop of a key, restoring its",
+        "                previous value or absence",
+        "    stats       Print a summary of the depot's contents",
+        "    list        Print every key in the depot, or every key matching the",
+        "                glob pattern given in place of a key, one per line",
+        "    edit        Edit the value associated with the given key in $EDITOR",
+        "                (Use -s to encrypt a new value)",
+        "    repl        Read actions from stdin, one per line with their keys and",
+        "                options, asking for the master password at most once",
+        "                (Type exit or quit, or end input, to leave; use -t to",
+        "                forget the password after a time without an action)",
         "",
         "Options:",
         "    -n          No newline character will be printed after fetching a value",
         "    -s          The provided value is secret and will be encrypted",
+        "    -r          Stow all of stdin exactly as read, keeping leading and",
+        "                trailing whitespace and newlines, except the final newline",
+        "                of a secret, as when it is typed (Also --raw)",
+        "    -m          Stow all of stdin, not just its first line, less leading and",
+        "                trailing whitespace, e.g. for notes or PEM keys; a secret",
+        "                typed on the terminal ends with Ctrl-D (Also --multiline)",
+        "    -p <file>   Read the password from the given file",
+        "                (Also --password-file <file>)",
+        "    -f <file>   Stow the contents of the given file instead of reading stdin",
+        "    -o <file>   Write the fetched value exactly to the given file instead of",
+        "                printing it, creating the file readable only by you",
+        "                (Also --output <file>)",
+        "    -c          Copy the fetched value to the clipboard instead of printing",
+        "                it and clear it after 45 seconds, if built with the",
+        "                clipboard feature (Also --clipboard)",
+        "    --keep-newline",
+        "                Keep a trailing newline at the end of the file given to -f",
+        "    -d <path>   Use the database at the given path, overriding DEPOT_PATH",
+        "                and the config file (Also --database <path>; not -f, which",
+        "                names the file whose contents stow reads)",
+        "    -l <length> Length of a generated value (Defaults to 20)",
+        "                (Also --length <length>)",
+        "    -t <secs>   Forget the master password remembered by repl after the",
+        "                given number of seconds without an action, asking for it",
+        "                again when next needed (Also --timeout <secs>)",
+        "    -a          Generate only letters and digits instead of all printable",
+        "                characters (Same as --charset alphanumeric)",
+        "    --charset <set>",
+        "                Generate from digits, alphanumeric, or printable characters,",
+        "                or from exactly the characters given instead, e.g.",
+        "                '0123456789abcdef' (Defaults to printable)",
+        "    -e          Print the generated value after stowing it",
+        "    -q          Print nothing to stderr but errors (Also --quiet)",
+        "    -y          Drop or clear without asking for confirmation, as is",
+        "                required when stdin is not a terminal (Also --yes)",
+        "    -g          Drop every key matching the glob pattern given in place of",
+        "                a key, e.g. 'old/*' (Also --glob)",
+        "    --force     Let stow replace the value of a key that already exists,",
+        "                which it otherwise refuses to do",
+        "    --dry-run   Print the keys that drop, clear, or prune would remove,",
+        "                and how many, without removing anything (Only with those)",
+        "    -0          List keys separated by NUL characters instead of newlines,",
+        "                e.g. for xargs -0 (Also --null)",
+        "    --count     Print the number of keys list would print instead",
+        "    --json      Print the output of fetch, list, and stats as JSON",
         "    -h, -?      Print this help message and exit",
+        "    -V          Print the version and exit (Also --version)",
+        "",
+        "Exit Status:",
+        "    0           Success",
+        "    1           General failure",
+        "    2           The given key or the depot itself does not exist",
+        "    3           A password is required or incorrect",
+        "    64          Invalid command-line usage or key",
+        "",
+        "Config File:",
+        "    $XDG_CONFIG_HOME/depot/config may set these defaults, one",
+        "    name = value per line (blank lines and lines starting with #",
+        "    are ignored), which the environment variables below override:",
+        "    path = \"<path>\"        The path to the depot's database",
+        "    kdf_iterations = <n>   PBKDF2 iterations for newly encrypted values",
+        "                           (Defaults to 4096)",
+        "    cipher = \"aes-256-gcm\" The cipher (the only one supported)",
         "",
         "Environment Variables:",
         "    DEPOT_PATH  Specifies a non-standard path to the depot's database",
         "                (Defaults to $XDG_CONFIG_HOME/depot/depot.db)",
         "    DEPOT_PASS  Specifies the password to be used to encrypt/decrypt values",
         "                (Be careful with this! It is certainly less secure!)",
+        "    DEPOT_PASS_FILE",
+        "                Specifies a file from which to read the password",
+        "                (Takes precedence over DEPOT_PASS; -p takes precedence over both)",
     ]
     .join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piped(input: &str, secret: bool, raw: bool, multiline: bool) -> Result<String> {
+        read_input(&mut io::Cursor::new(input), secret, raw, multiline)
+    }
+
+    #[test]
+    fn test_piped_secret_matches_typed() {
+        // A typed secret is read without its newline and then trimmed.
+        let typed = check_val("  hunter2 ", false).unwrap();
+        assert_eq!(typed, "hunter2");
+        assert_eq!(piped("  hunter2 \n", true, false, false).unwrap(), typed);
+        assert_eq!(piped("  hunter2 \r\n", true, false, false).unwrap(), typed);
+        assert_eq!(piped("hunter2\nrest\n", true, false, false).unwrap(), typed);
+        assert_eq!(piped("hunter2", true, false, false).unwrap(), typed);
+
+        let typed = check_val(" hunter2 ", true).unwrap();
+        assert_eq!(piped(" hunter2 \n", true, true, false).unwrap(), typed);
+        assert_eq!(piped(" hunter2 \r\n", true, true, false).unwrap(), typed);
+        assert_eq!(piped(" hunter2 ", true, true, false).unwrap(), typed);
+
+        assert_eq!(piped("a\nb\n", true, false, true).unwrap(), "a\nb");
+        assert_eq!(piped("a\nb\n", true, true, true).unwrap(), "a\nb");
+        assert!(piped("\n", true, false, false).is_err());
+        assert!(piped("", true, true, false).is_err());
+    }
+
+    #[test]
+    fn test_piped_plain_value() {
+        assert_eq!(
+            piped(" value \nrest", false, false, false).unwrap(),
+            "value"
+        );
+        assert_eq!(piped(" a\nb \n", false, false, true).unwrap(), "a\nb");
+        assert_eq!(piped(" a\nb \n", false, true, false).unwrap(), " a\nb \n");
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let args = |a: &[&str]| a.iter().map(|s| String::from(*s)).collect::<Vec<_>>();
+
+        let given = args(&["-pf", "pw.txt", "val.txt", "stow", "key"]);
+        let opts = parse_args(&given).unwrap();
+        assert_eq!(opts.pass_file, Some("pw.txt"));
+        assert_eq!(opts.file, Some("val.txt"));
+        let given = args(&["-fp", "val.txt", "pw.txt", "stow", "key"]);
+        let opts = parse_args(&given).unwrap();
+        assert_eq!(opts.pass_file, Some("pw.txt"));
+        assert_eq!(opts.file, Some("val.txt"));
+
+        assert!(parse_args(&args(&["--verbose", "fetch", "key"])).is_err());
+        assert!(parse_args(&args(&["--file", "a.db", "fetch", "key"])).is_err());
+        assert!(parse_args(&args(&["-x", "fetch", "key"])).is_err());
+        assert!(parse_args(&args(&["-", "fetch", "key"])).is_err());
+        assert!(parse_args(&args(&["-sq", "fetch", "key"])).is_ok());
+        assert!(!parse_args(&args(&["stow", "key"])).unwrap().force);
+        assert!(
+            parse_args(&args(&["--force", "stow", "key"]))
+                .unwrap()
+                .force
+        );
+        assert!(parse_args(&args(&["--no-clobber", "stow", "key"])).is_err());
+        assert!(parse_args(&args(&["--dry-run", "prune"])).unwrap().dry_run);
+        assert!(parse_args(&args(&["--dry-run", "stow", "key"])).is_err());
+    }
+
+    #[test]
+    fn test_session_timeout() {
+        let path = env::temp_dir().join(format!("depot-session-{}.db", process::id()));
+        let mut storage = Depot::open_path(&path).unwrap();
+        storage
+            .stow("secret", "testing123", Some("password"))
+            .unwrap();
+        let mut session = Session {
+            remember: true,
+            timeout: Some(Duration::from_secs(60)),
+            last: None,
+        };
+        session
+            .remember(&mut storage, &Zeroizing::new(String::from("password")))
+            .unwrap();
+        assert_eq!(storage.unlocked_password(), Some("password"));
+        session.touch(&mut storage, true);
+        assert!(storage.is_unlocked());
+        session.last = Some(Instant::now() - Duration::from_secs(30));
+        session.touch(&mut storage, true);
+        assert!(storage.is_unlocked());
+        session.last = Some(Instant::now() - Duration::from_secs(61));
+        session.touch(&mut storage, true);
+        assert!(!storage.is_unlocked());
+        drop(storage);
+        for ext in ["", "-wal", "-shm"] {
+            let mut file = path.clone().into_os_string();
+            file.push(ext);
+            let _ = std::fs::remove_file(file);
+        }
+
+        assert_eq!(parse_timeout("300").unwrap(), Duration::from_secs(300));
+        assert!(parse_timeout("0").is_err());
+        assert!(parse_timeout("soon").is_err());
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = parse_config(
+            "# defaults\n\
+            \n\
+            path = \"/tmp/depot.db\"\n\
+            \tkdf_iterations=8192\n\
+            cipher = AES-256-GCM\n",
+        )
+        .unwrap();
+        assert_eq!(config.path.as_deref(), Some("/tmp/depot.db"));
+        assert_eq!(config.kdf_iterations, Some(8192));
+
+        let config = parse_config("  # path = ignored\n").unwrap();
+        assert!(config.path.is_none() && config.kdf_iterations.is_none());
+
+        let err = |text| parse_config(text).err().unwrap().to_string();
+        assert_eq!(err("colour = blue"), "unrecognized config setting: colour");
+        assert_eq!(err("\npath"), "config line 2 is malformed");
+        assert_eq!(err("path = \"/tmp/depot.db"), "config line 1 is malformed");
+        assert_eq!(
+            err("kdf_iterations = many"),
+            "kdf_iterations must be a number"
+        );
+        assert_eq!(err("cipher = des"), "unsupported cipher: des");
+    }
+}
@@ -0,0 +1,277 @@
+//! Ordered schema migrations, applied whenever a depot is opened.
+//! The database records how many migrations it has had applied in its
+//! `schema_version` table, so each migration runs exactly once. Migrations
+//! must only ever be appended to `MIGRATIONS`, never reordered or removed.
+
+use crate::sql::{Prefixed, Sql, Tx};
+use rusqlite::Connection;
+
+type Migration = fn(&Tx) -> rusqlite::Result<()>;
+
+/// Every migration in the order in which it is applied.
+/// A database at version n has had the first n of these applied.
+const MIGRATIONS: &[Migration] = &[
+    create_storage,
+    create_meta,
+    add_custom,
+    add_compressed,
+    drop_unique_nonce,
+    add_bound,
+    add_iterations,
+    add_kdf,
+    add_store,
+    add_expires,
+    add_access,
+    create_history,
+    add_deleted,
+    add_hint,
+    add_tags,
+    create_journal,
+];
+
+/// Applies every migration the database has not yet had applied, each in
+/// its own transaction along with the version bump that records it.
+/// Returns an error if any migration fails, leaving that migration unapplied.
+pub(crate) fn run(db: &mut Connection, prefix: &str) -> rusqlite::Result<()> {
+    let version: usize = {
+        let db = Prefixed { db, prefix };
+        db.execute_batch(
+            "create table if not exists schema_version (
+                version int not null
+            );
+
+            insert into schema_version (version)
+            select 0
+            where not exists (select 1 from schema_version);",
+        )?;
+        db.query_row("select version from schema_version", (), |row| row.get(0))?
+    };
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = Tx {
+            tx: db.transaction()?,
+            prefix,
+        };
+        migration(&tx)?;
+        tx.execute("update schema_version set version = ?1", (i + 1,))?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Adds an integer column with a default of 0 to the storage table,
+/// unless a database created before migrations were tracked already has it.
+fn add_flag_column(db: &Tx, col: &str) -> rusqlite::Result<()> {
+    if db.prepare(&format!("select {} from storage", col)).is_err() {
+        db.execute(
+            &format!(
+                "alter table storage add column {} int not null default 0",
+                col
+            ),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+/// Creates the original storage and salt tables.
+fn create_storage(db: &Tx) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "create table if not exists storage (
+            modified   int  default (strftime('%s', 'now')),
+            key        text unique not null,
+            val        text not null,
+            nonce      blob unique
+        );
+
+        create table if not exists salt (
+            data blob not null
+        );",
+    )
+}
+
+/// Creates the meta table, which holds the password-verification sentinel.
+fn create_meta(db: &Tx) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "create table if not exists meta (
+            name       text unique not null,
+            val        text not null,
+            nonce      blob
+        );",
+    )
+}
+
+/// Adds the flag marking values encrypted with a custom password.
+fn add_custom(db: &Tx) -> rusqlite::Result<()> {
+    add_flag_column(db, "custom")
+}
+
+/// Adds the flag marking compressed values.
+fn add_compressed(db: &Tx) -> rusqlite::Result<()> {
+    add_flag_column(db, "compressed")
+}
+
+/// Rebuilds the storage table without the unique constraint on nonce,
+/// which SQLite cannot drop in place.
+fn drop_unique_nonce(db: &Tx) -> rusqlite::Result<()> {
+    let unique_nonce: bool = db.query_row(
+        "select count(*) > 0
+        from pragma_index_list('storage') as l
+        join pragma_index_info(l.name) as i
+        where l.\"unique\" and i.name = 'nonce'",
+        (),
+        |row| row.get(0),
+    )?;
+    if !unique_nonce {
+        return Ok(());
+    }
+
+    db.execute_batch(
+        "create table storage_new (
+            modified   int  default (strftime('%s', 'now')),
+            key        text unique not null,
+            val        text not null,
+            nonce      blob,
+            custom     int  not null default 0,
+            compressed int  not null default 0
+        );
+
+        insert into storage_new (modified, key, val, nonce, custom, compressed)
+        select modified, key, val, nonce, custom, compressed
+        from storage;
+
+        drop table storage;
+        alter table storage_new rename to storage;",
+    )
+}
+
+/// Adds the flag marking encrypted values bound to their key.
+fn add_bound(db: &Tx) -> rusqlite::Result<()> {
+    add_flag_column(db, "bound")
+}
+
+/// Adds the PBKDF2 iteration count each value and the sentinel were
+/// encrypted with, which was fixed at 4096 before it was configurable.
+fn add_iterations(db: &Tx) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "alter table storage add column iterations int not null default 4096;
+        alter table meta add column iterations int not null default 4096;",
+    )
+}
+
+/// Adds the hash each value and the sentinel were encrypted with,
+/// which was always SHA-1 (0) before SHA-256 (1) was supported.
+fn add_kdf(db: &Tx) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "alter table storage add column kdf int not null default 0;
+        alter table meta add column kdf int not null default 0;",
+    )
+}
+
+/// Rebuilds the storage table with the store each key belongs to, the
+/// default store being the empty string, so that keys need only be unique
+/// within their store.
+fn add_store(db: &Tx) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "create table storage_new (
+            modified   int  default (strftime('%s', 'now')),
+            store      text not null default '',
+            key        text not null,
+            val        text not null,
+            nonce      blob,
+            custom     int  not null default 0,
+            compressed int  not null default 0,
+            bound      int  not null default 0,
+            iterations int  not null default 4096,
+            kdf        int  not null default 0,
+            unique (store, key)
+        );
+
+        insert into storage_new
+            (modified, key, val, nonce, custom, compressed, bound, iterations, kdf)
+        select modified, key, val, nonce, custom, compressed, bound, iterations, kdf
+        from storage;
+
+        drop table storage;
+        alter table storage_new rename to storage;",
+    )
+}
+
+/// Adds the Unix timestamp after which each value expires, if it does.
+fn add_expires(db: &Tx) -> rusqlite::Result<()> {
+    db.execute("alter table storage add column expires int", ())?;
+    Ok(())
+}
+
+/// Adds the Unix timestamp each value was last fetched and the number of
+/// times it has been, both of which are only recorded when tracking access.
+fn add_access(db: &Tx) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "alter table storage add column accessed int;
+        alter table storage add column access_count int not null default 0;",
+    )
+}
+
+/// Creates the history table, which holds previous versions of values.
+fn create_history(db: &Tx) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "create table history (
+            store      text not null,
+            key        text not null,
+            version    int  not null,
+            modified   int,
+            val        text not null,
+            nonce      blob,
+            custom     int  not null,
+            compressed int  not null,
+            bound      int  not null,
+            kdf        int  not null,
+            iterations int  not null,
+            primary key (store, key, version)
+        );",
+    )
+}
+
+/// Adds the Unix timestamp at which each value in the trash was dropped.
+fn add_deleted(db: &Tx) -> rusqlite::Result<()> {
+    db.execute("alter table storage add column deleted int", ())?;
+    Ok(())
+}
+
+/// Adds the unencrypted hint to the password each value is encrypted with.
+fn add_hint(db: &Tx) -> rusqlite::Result<()> {
+    db.execute("alter table storage add column hint text", ())?;
+    Ok(())
+}
+
+/// Adds the unencrypted, comma-separated tags of each key.
+fn add_tags(db: &Tx) -> rusqlite::Result<()> {
+    db.execute("alter table storage add column tags text", ())?;
+    Ok(())
+}
+
+/// Creates the journal table, which holds, for each store, the row of the
+/// key most recently stowed or dropped as it was beforehand, or only its key
+/// if it had none, so that `undo` can put it back.
+fn create_journal(db: &Tx) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "create table journal (
+            store        text not null primary key,
+            key          text not null,
+            modified     int,
+            val          text,
+            nonce        blob,
+            custom       int,
+            compressed   int,
+            bound        int,
+            iterations   int,
+            kdf          int,
+            expires      int,
+            accessed     int,
+            access_count int,
+            deleted      int,
+            hint         text,
+            tags         text
+        );",
+    )
+}
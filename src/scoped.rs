@@ -0,0 +1,61 @@
+//! A view of a depot confined to the keys under one prefix, so that
+//! different components can share a depot without their keys colliding.
+
+use crate::{Depot, Result};
+
+/// A depot of which only the keys beginning with a prefix and a slash are
+/// visible, with the prefix left off. Storing "token" in the view scoped to
+/// "mail" stores "mail/token" in the underlying depot.
+pub struct ScopedDepot<'a> {
+    depot: &'a Depot,
+    prefix: String,
+}
+
+impl<'a> ScopedDepot<'a> {
+    pub(crate) fn new(depot: &'a Depot, prefix: &str) -> ScopedDepot<'a> {
+        ScopedDepot {
+            depot,
+            prefix: format!("{}/", prefix),
+        }
+    }
+
+    /// Returns the prefix of every key in the view, including the slash.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Stores the specified key and value under the prefix as with
+    /// `Depot::stow`.
+    pub fn stow(&self, key: &str, val: &str, password: Option<&str>) -> Result<()> {
+        self.depot.stow(&self.scope(key)?, val, password)
+    }
+
+    /// Returns the value associated with the specified key under the prefix
+    /// as with `Depot::fetch`.
+    pub fn fetch(&self, key: &str, password: Option<&str>) -> Result<String> {
+        self.depot.fetch(&self.scope(key)?, password)
+    }
+
+    /// Removes the specified key under the prefix as with `Depot::drop`.
+    pub fn drop(&self, key: &str) -> Result<bool> {
+        self.depot.drop(&self.scope(key)?)
+    }
+
+    /// Returns every key under the prefix, in order and without the prefix,
+    /// or an error if unsuccessful.
+    pub fn keys(&self) -> Result<Vec<String>> {
+        Ok(self
+            .depot
+            .keys()?
+            .into_iter()
+            .filter_map(|k| k.strip_prefix(&self.prefix).map(String::from))
+            .collect())
+    }
+
+    /// Returns the given key with the prefix prepended, or an error if the
+    /// key is invalid as with `Depot::stow`.
+    fn scope(&self, key: &str) -> Result<String> {
+        self.depot.validate_key(key)?;
+        Ok(format!("{}{}", self.prefix, key))
+    }
+}
@@ -0,0 +1,139 @@
+//! Running the depot's SQL against tables whose names may carry a prefix,
+//! so that a depot can share a database with an application's own tables.
+//! SQL is written with the plain table names, which are rewritten to carry
+//! the prefix, if any, just before it is run.
+
+use std::borrow::Cow;
+
+/// The names of the tables (including those only briefly created by
+/// migrations) that a depot owns and that carry its table prefix.
+const TABLES: &[&str] = &[
+    "storage",
+    "storage_new",
+    "salt",
+    "meta",
+    "history",
+    "journal",
+    "schema_version",
+];
+
+/// Returns the given SQL with every one of the depot's table names in it
+/// carrying the given prefix.
+pub(crate) fn prefixed<'a>(prefix: &str, sql: &'a str) -> Cow<'a, str> {
+    if prefix.is_empty() {
+        return Cow::Borrowed(sql);
+    }
+
+    let mut out = String::with_capacity(sql.len() + prefix.len() * 4);
+    let mut word = None;
+    for (i, c) in sql.char_indices().chain([(sql.len(), ' ')]) {
+        let ident = c.is_ascii_alphanumeric() || c == '_';
+        match word {
+            None if ident => word = Some(i),
+            Some(start) if !ident => {
+                if TABLES.contains(&&sql[start..i]) {
+                    out.push_str(prefix);
+                }
+                out.push_str(&sql[start..i]);
+                word = None;
+            }
+            _ => {}
+        }
+        if !ident && i < sql.len() {
+            out.push(c);
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Returns whether the given table prefix is safe to put in SQL: empty, or
+/// an ASCII letter or underscore followed by ASCII letters, digits, and
+/// underscores.
+pub(crate) fn valid_prefix(prefix: &str) -> bool {
+    let mut chars = prefix.chars();
+    match chars.next() {
+        None => true,
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        Some(_) => false,
+    }
+}
+
+/// A database connection that runs SQL naming the depot's tables as if they
+/// carried no prefix.
+pub(crate) trait Sql {
+    /// Returns the underlying connection.
+    fn connection(&self) -> &rusqlite::Connection;
+
+    /// Returns the prefix the depot's tables carry.
+    fn prefix(&self) -> &str;
+
+    fn execute<P: rusqlite::Params>(&self, sql: &str, params: P) -> rusqlite::Result<usize> {
+        self.connection()
+            .execute(&prefixed(self.prefix(), sql), params)
+    }
+
+    fn execute_batch(&self, sql: &str) -> rusqlite::Result<()> {
+        self.connection()
+            .execute_batch(&prefixed(self.prefix(), sql))
+    }
+
+    fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> rusqlite::Result<T>
+    where
+        P: rusqlite::Params,
+        F: FnOnce(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    {
+        self.connection()
+            .query_row(&prefixed(self.prefix(), sql), params, f)
+    }
+
+    fn prepare(&self, sql: &str) -> rusqlite::Result<rusqlite::Statement<'_>> {
+        self.connection().prepare(&prefixed(self.prefix(), sql))
+    }
+
+    fn prepare_cached(&self, sql: &str) -> rusqlite::Result<rusqlite::CachedStatement<'_>> {
+        self.connection()
+            .prepare_cached(&prefixed(self.prefix(), sql))
+    }
+}
+
+/// A connection paired with the prefix its depot's tables carry.
+pub(crate) struct Prefixed<'a> {
+    pub(crate) db: &'a rusqlite::Connection,
+    pub(crate) prefix: &'a str,
+}
+
+impl Sql for Prefixed<'_> {
+    fn connection(&self) -> &rusqlite::Connection {
+        self.db
+    }
+
+    fn prefix(&self) -> &str {
+        self.prefix
+    }
+}
+
+/// A transaction on a depot's connection, which is rolled back if it is
+/// dropped without being committed.
+pub(crate) struct Tx<'a> {
+    pub(crate) tx: rusqlite::Transaction<'a>,
+    pub(crate) prefix: &'a str,
+}
+
+impl Tx<'_> {
+    /// Commits the transaction or returns an error if unsuccessful.
+    pub(crate) fn commit(self) -> rusqlite::Result<()> {
+        self.tx.commit()
+    }
+}
+
+impl Sql for Tx<'_> {
+    fn connection(&self) -> &rusqlite::Connection {
+        &self.tx
+    }
+
+    fn prefix(&self) -> &str {
+        self.prefix
+    }
+}
@@ -0,0 +1,55 @@
+//! A rough estimate of password strength, to warn about weak passwords.
+
+/// How hard a password is estimated to be to guess.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strength {
+    /// Under 40 bits of estimated entropy, within reach of offline guessing.
+    Weak,
+    /// At least 40 but under 64 bits of estimated entropy.
+    Fair,
+    /// At least 64 bits of estimated entropy.
+    Strong,
+}
+
+/// Returns an estimate of how hard the given password is to guess. The
+/// estimate is the number of characters, not counting any that repeat or
+/// continue a run like "abc" or "321" from the previous character, times the
+/// bits each character could carry given the kinds of characters (lowercase,
+/// uppercase, digits, ASCII punctuation, and others) the password contains.
+/// It knows nothing of dictionary words, so it is only advisory: a phrase of
+/// common words may be stronger, and a mangled common word weaker, than it
+/// accounts for.
+pub fn password_strength(password: &str) -> Strength {
+    let chars: Vec<char> = password.chars().collect();
+    let has = |f: fn(&char) -> bool| chars.iter().any(f);
+    let pool = [
+        (has(char::is_ascii_lowercase), 26),
+        (has(char::is_ascii_uppercase), 26),
+        (has(char::is_ascii_digit), 10),
+        (has(char::is_ascii_punctuation), 32),
+        (has(|c| c.is_ascii_whitespace()), 1),
+        (has(|c| !c.is_ascii()), 100),
+    ]
+    .iter()
+    .filter(|(present, _)| *present)
+    .map(|(_, size)| size)
+    .sum::<u32>();
+
+    let counted = chars
+        .iter()
+        .enumerate()
+        .filter(|(i, &c)| match i.checked_sub(1).map(|j| chars[j]) {
+            Some(prev) => (c as i64 - prev as i64).abs() > 1,
+            None => true,
+        })
+        .count();
+
+    let bits = counted as f64 * f64::from(pool.max(1)).log2();
+    if bits < 40.0 {
+        Strength::Weak
+    } else if bits < 64.0 {
+        Strength::Fair
+    } else {
+        Strength::Strong
+    }
+}
@@ -1,13 +1,49 @@
 #[cfg(test)]
 mod tests {
-    const DB_PATH: &str = "./test.db";
+    use std::ffi::{OsStr, OsString};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A database of its own for one test, under the system temp directory.
+    /// The file and its WAL and journal siblings are removed when the test
+    /// ends, whether it passes or not.
+    struct TempDb(PathBuf);
+
+    impl TempDb {
+        fn new() -> TempDb {
+            TempDb::with_suffix(OsStr::new(".db"))
+        }
+
+        fn with_suffix(suffix: &OsStr) -> TempDb {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let n = NEXT.fetch_add(1, Ordering::Relaxed);
+            let mut name = OsString::from(format!("depot-test-{}-{}", std::process::id(), n));
+            name.push(suffix);
+            TempDb(std::env::temp_dir().join(name))
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            for ext in ["", "-wal", "-shm", "-journal"] {
+                let mut path = self.0.clone().into_os_string();
+                path.push(ext);
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
 
     #[test]
     fn test_plain() {
         let key = "plaintext";
         let data = "testing123";
 
-        let storage = depot::Depot::new(DB_PATH).unwrap();
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
         assert!(storage.stow(key, data, None).is_ok());
 
         let val = storage.fetch(key, None).unwrap();
@@ -23,7 +59,8 @@ mod tests {
         let data = "testing123";
         let password = "password";
 
-        let storage = depot::Depot::new(DB_PATH).unwrap();
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
         assert!(storage.stow(key, data, Some(password)).is_ok());
 
         let val = storage.fetch(key, Some(password)).unwrap();
@@ -40,16 +77,2726 @@ mod tests {
         let goodpassword = "goodpassword";
         let badpassword = "badpassword";
 
-        let storage = depot::Depot::new(DB_PATH).unwrap();
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
         assert!(storage.stow(key, data, Some(goodpassword)).is_ok());
 
         assert!(storage.fetch(key, Some(badpassword)).is_err());
         assert!(storage.drop(key).is_ok());
     }
 
+    #[test]
+    fn test_verify_password() {
+        let key = "verifypassword";
+        let data = "testing123";
+        let password = "password";
+
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow(key, data, Some(password)).is_ok());
+
+        assert!(storage.verify_password(password).unwrap());
+        assert!(!storage.verify_password("wrongpassword").unwrap());
+        assert!(storage.drop(key).is_ok());
+
+        assert!(storage.verify_password(password).unwrap());
+        assert!(!storage.verify_password("wrongpassword").unwrap());
+    }
+
+    #[test]
+    fn test_verify_password_without_sentinel() {
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.verify_password("anything").unwrap());
+        assert!(storage.stow("master", "val", Some("password")).is_ok());
+        assert!(storage.stow("custom", "val", Some("other")).is_ok());
+        let work = depot::Depot::new_named(db.path(), "work").unwrap();
+        assert!(work.stow("work", "val", Some("other")).is_ok());
+
+        let conn = rusqlite::Connection::open(db.path()).unwrap();
+        conn.execute_batch(
+            "delete from meta where name = 'sentinel';
+            update storage set rowid = -1 where key = 'custom';
+            update storage set rowid = -2, custom = 0 where key = 'work';",
+        )
+        .unwrap();
+        assert!(storage.verify_password("password").unwrap());
+        assert!(!storage.verify_password("other").unwrap());
+    }
+
+    #[test]
+    fn test_first_sentinel_without_sentinel() {
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow("old", "testing123", Some("password")).is_ok());
+        let conn = rusqlite::Connection::open(db.path()).unwrap();
+        conn.execute("delete from meta where name = 'sentinel'", ())
+            .unwrap();
+
+        assert!(storage.stow("new", "testing456", Some("other")).is_ok());
+        assert!(storage.has_custom_password("new").unwrap());
+        assert!(storage.verify_password("password").unwrap());
+        assert!(!storage.verify_password("other").unwrap());
+
+        assert!(storage
+            .stow("newer", "testing789", Some("password"))
+            .is_ok());
+        assert!(!storage.has_custom_password("newer").unwrap());
+        assert_eq!(storage.change_password("password", "changed").unwrap(), 2);
+        assert_eq!(storage.fetch("old", Some("changed")).unwrap(), "testing123");
+        assert_eq!(storage.fetch("new", Some("other")).unwrap(), "testing456");
+    }
+
+    #[test]
+    fn test_master_password() {
+        let master = "masterpassword";
+        let custom = "custompassword";
+        let newmaster = "newmasterpassword";
+
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow("master", "testing123", Some(master)).is_ok());
+        assert!(storage.stow("custom", "testing456", Some(custom)).is_ok());
+
+        assert!(!storage.has_custom_password("master").unwrap());
+        assert!(storage.has_custom_password("custom").unwrap());
+
+        assert!(storage.change_password(custom, newmaster).is_err());
+        assert_eq!(storage.change_password(master, newmaster).unwrap(), 1);
+        assert_eq!(
+            storage.fetch("master", Some(newmaster)).unwrap(),
+            "testing123"
+        );
+        assert_eq!(storage.fetch("custom", Some(custom)).unwrap(), "testing456");
+        assert!(storage.verify_password(newmaster).unwrap());
+    }
+
+    #[test]
+    fn test_generate() {
+        let key = "generate";
+        let password = "password";
+
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        let opts = depot::GenOptions {
+            length: 32,
+            charset: depot::Charset::Alphanumeric,
+        };
+        let val = storage.generate(key, opts, Some(password)).unwrap();
+        assert_eq!(val.len(), 32);
+        assert!(val.chars().all(|c| depot::ALPHANUMERIC.contains(c)));
+        assert_eq!(storage.fetch(key, Some(password)).unwrap(), *val);
+
+        let val = storage.generate(key, Default::default(), None).unwrap();
+        assert_eq!(val.len(), 20);
+        assert!(val.chars().all(|c| depot::PRINTABLE.contains(c)));
+    }
+
+    #[test]
+    fn test_generate_charset() {
+        let key = "generate";
+
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        let opts = depot::GenOptions {
+            length: 64,
+            charset: depot::Charset::Digits,
+        };
+        let val = storage.generate(key, opts, None).unwrap();
+        assert!(val.chars().all(|c| c.is_ascii_digit()));
+        let opts = depot::GenOptions {
+            length: 64,
+            charset: depot::Charset::Custom(String::from("ab!ab")),
+        };
+        let val = storage.generate(key, opts, None).unwrap();
+        assert!(val.chars().all(|c| "ab!".contains(c)));
+    }
+
+    #[test]
+    fn test_generate_invalid() {
+        let key = "generate";
+
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        let opts = depot::GenOptions {
+            length: 0,
+            ..Default::default()
+        };
+        assert!(storage.generate(key, opts, None).is_err());
+        let opts = depot::GenOptions {
+            charset: depot::Charset::Custom(String::new()),
+            ..Default::default()
+        };
+        assert!(storage.generate(key, opts, None).is_err());
+        assert!(matches!(
+            storage.fetch(key, None),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_compress() {
+        let data = "testing123".repeat(100);
+        let password = "password";
+        let opts = depot::Options {
+            compress: true,
+            ..Default::default()
+        };
+
+        let db = TempDb::new();
+        let storage = depot::Depot::with_options(db.path(), opts).unwrap();
+        assert!(storage.stow("compressplain", &data, None).is_ok());
+        assert!(storage
+            .stow("compresscipher", &data, Some(password))
+            .is_ok());
+        assert!(storage.stow("compresstiny", "x", None).is_ok());
+
+        let plain = depot::Depot::new(db.path()).unwrap();
+        assert_eq!(plain.fetch("compressplain", None).unwrap(), data);
+        assert_eq!(plain.fetch("compresscipher", Some(password)).unwrap(), data);
+        assert_eq!(plain.fetch("compresstiny", None).unwrap(), "x");
+    }
+
+    #[test]
+    fn test_touch() {
+        let key = "touch";
+
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow(key, "testing123", None).is_ok());
+        assert!(storage.touch(key).is_ok());
+        assert_eq!(storage.fetch(key, None).unwrap(), "testing123");
+
+        assert!(storage.drop(key).is_ok());
+        assert!(matches!(storage.touch(key), Err(depot::Error::NotFound(_))));
+    }
+
+    #[test]
+    fn test_modified_since() {
+        let key = "modifiedsince";
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow(key, "testing123", None).is_ok());
+        assert!(storage
+            .modified_since(now - 60)
+            .unwrap()
+            .contains(&String::from(key)));
+        assert!(!storage
+            .modified_since(now + 60)
+            .unwrap()
+            .contains(&String::from(key)));
+    }
+
+    #[test]
+    fn test_copy() {
+        let data = "testing123";
+        let password = "password";
+
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow("copyplain", data, None).is_ok());
+        assert!(storage.stow("copycipher", data, Some(password)).is_ok());
+
+        assert!(storage.copy("copyplain", "copyplain2", None).is_ok());
+        assert_eq!(storage.fetch("copyplain2", None).unwrap(), data);
+
+        assert!(matches!(
+            storage.copy("copycipher", "copycipher2", None),
+            Err(depot::Error::NeedPassword)
+        ));
+        assert!(storage
+            .copy("copycipher", "copycipher2", Some(password))
+            .is_ok());
+        assert_eq!(storage.fetch("copycipher2", Some(password)).unwrap(), data);
+
+        assert!(storage
+            .stow_with_hint("copyhint", data, Some(password), "the usual")
+            .is_ok());
+        assert!(storage.tag("copyhint", &["work"]).is_ok());
+        assert!(storage
+            .copy("copyhint", "copyhint2", Some(password))
+            .is_ok());
+        assert_eq!(
+            storage.hint("copyhint2").unwrap().as_deref(),
+            Some("the usual")
+        );
+        assert_eq!(storage.tags("copyhint2").unwrap(), vec!["work"]);
+
+        assert!(matches!(
+            storage.copy("copyplain", "copyplain2", None),
+            Err(depot::Error::AlreadyExists(_))
+        ));
+    }
+
+    #[test]
+    fn test_swapped_ciphertext() {
+        let password = "password";
+        let db = TempDb::new();
+        let path = db.path();
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage.stow("swapa", "testing123", Some(password)).is_ok());
+        assert!(storage.stow("swapb", "testing456", Some(password)).is_ok());
+
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute(
+            "update storage
+            set (val, nonce) = (select val, nonce from storage where key = 'swapb')
+            where key = 'swapa'",
+            (),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            storage.fetch("swapa", Some(password)),
+            Err(depot::Error::DecryptFailed(k)) if k == "swapa"
+        ));
+        assert!(matches!(
+            storage.fetch("swapa", Some("wrong")),
+            Err(depot::Error::BadPassword)
+        ));
+    }
+
+    #[test]
+    fn test_bad_nonce() {
+        let db = TempDb::new();
+        let path = db.path();
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage.stow("short", "val", Some("password")).is_ok());
+        assert!(storage.stow("long", "val", Some("password")).is_ok());
+
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute(
+            "update storage set nonce = substr(nonce, 1, 8) where key = 'short'",
+            (),
+        )
+        .unwrap();
+        conn.execute(
+            "update storage set nonce = cast(nonce || nonce as blob) where key = 'long'",
+            (),
+        )
+        .unwrap();
+
+        for key in ["short", "long"] {
+            assert!(matches!(
+                storage.fetch(key, Some("password")),
+                Err(depot::Error::DecryptFailed(k)) if k == key
+            ));
+        }
+        assert_eq!(storage.check("password").unwrap(), vec!["long", "short"]);
+        assert!(storage.change_password("password", "new").is_err());
+    }
+
+    #[test]
+    fn test_malformed_value() {
+        let db = TempDb::new();
+        let path = db.path();
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage.stow("good", "val", Some("password")).is_ok());
+        assert!(storage.stow("bad", "val", Some("password")).is_ok());
+
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute(
+            "update storage set val = 'not base64!' where key = 'bad'",
+            (),
+        )
+        .unwrap();
+
+        let err = storage.fetch("bad", Some("password")).unwrap_err();
+        assert!(matches!(&err, depot::Error::Malformed(k, _) if k == "bad"));
+        assert_eq!(err.code(), depot::ErrorCode::Encoding);
+        assert!(err
+            .to_string()
+            .starts_with("the stored value of bad is not valid base64"));
+        let fetched = storage
+            .fetch_many(&["good", "bad"], Some("password"))
+            .unwrap();
+        assert_eq!(fetched[0].1.as_deref().unwrap(), "val");
+        assert!(matches!(&fetched[1].1, Err(depot::Error::Malformed(k, _)) if k == "bad"));
+        assert_eq!(storage.check("password").unwrap(), vec!["bad"]);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let db = TempDb::new();
+        let path = db.path();
+
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute_batch(
+            "create table storage (
+                modified   int  default (strftime('%s', 'now')),
+                key        text unique not null,
+                val        text not null,
+                nonce      blob unique
+            );
+            create table salt (data blob not null);
+            insert into salt (data) values (zeroblob(32));
+            insert into storage (key, val) values ('migrate', 'testing123');",
+        )
+        .unwrap();
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert_eq!(storage.fetch("migrate", None).unwrap(), "testing123");
+
+        let version: usize = conn
+            .query_row("select version from schema_version", (), |row| row.get(0))
+            .unwrap();
+        assert!(version > 0);
+        drop(storage);
+        depot::Depot::new(path).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let db = TempDb::new();
+        depot::Depot::new(db.path()).unwrap();
+
+        let writers: Vec<_> = (0..2)
+            .map(|i| {
+                let path = db.path().to_owned();
+                std::thread::spawn(move || {
+                    let storage = depot::Depot::new(&path).unwrap();
+                    for j in 0..50 {
+                        let key = format!("concurrent{}-{}", i, j);
+                        storage.stow(&key, "testing123", None).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for w in writers {
+            w.join().unwrap();
+        }
+
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert_eq!(storage.fetch("concurrent0-49", None).unwrap(), "testing123");
+        assert_eq!(storage.fetch("concurrent1-49", None).unwrap(), "testing123");
+    }
+
+    #[test]
+    fn test_backup() {
+        let key = "backup";
+        let password = "password";
+        let (db, copy) = (TempDb::new(), TempDb::new());
+
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow(key, "testing123", Some(password)).is_ok());
+        assert!(storage.backup(copy.path()).is_ok());
+        assert!(storage.drop(key).is_ok());
+
+        let restored = depot::Depot::new(copy.path()).unwrap();
+        assert_eq!(restored.fetch(key, Some(password)).unwrap(), "testing123");
+    }
+
+    #[test]
+    fn test_compact() {
+        let key = "compact";
+
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow(key, "testing123", None).is_ok());
+        assert!(storage.compact().is_ok());
+        assert_eq!(storage.fetch(key, None).unwrap(), "testing123");
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let db = TempDb::new();
+        let storage = std::sync::Arc::new(depot::Depot::new(db.path()).unwrap());
+
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let storage = std::sync::Arc::clone(&storage);
+                std::thread::spawn(move || {
+                    let key = format!("shared{}", i);
+                    storage.stow(&key, "testing123", None).unwrap();
+                    assert_eq!(storage.fetch(&key, None).unwrap(), "testing123");
+                    depot::Depot::drop(&storage, &key).unwrap();
+                })
+            })
+            .collect();
+        for w in workers {
+            w.join().unwrap();
+        }
+    }
+
+    #[cfg(feature = "pool")]
+    #[test]
+    fn test_pool() {
+        let db = TempDb::new();
+        let path = db.path();
+
+        let manager = r2d2_sqlite::SqliteConnectionManager::file(path)
+            .with_init(|c| c.busy_timeout(std::time::Duration::from_secs(5)));
+        let pool = r2d2::Pool::builder().max_size(4).build(manager).unwrap();
+        let storage =
+            std::sync::Arc::new(depot::Depot::from_pool(pool, depot::Options::default()).unwrap());
+        assert!(storage.stow("pool", "testing123", Some("password")).is_ok());
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let storage = std::sync::Arc::clone(&storage);
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        let val = storage.fetch("pool", Some("password")).unwrap();
+                        assert_eq!(val, "testing123");
+                    }
+                })
+            })
+            .collect();
+        for r in readers {
+            r.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        let password = "password";
+        let db = TempDb::new();
+        let path = db.path();
+
+        let storage = depot::Depot::new(path).unwrap();
+        for i in 0..250 {
+            let key = format!("iter{:03}", i);
+            let pw = if i % 50 == 1 { Some(password) } else { None };
+            assert!(storage.stow(&key, "testing123", pw).is_ok());
+        }
+
+        let keys: Vec<String> = storage.iter().unwrap().map(|e| e.unwrap().0).collect();
+        assert_eq!(keys.len(), 250);
+        assert_eq!(keys[0], "iter000");
+        assert_eq!(keys[249], "iter249");
+        assert_eq!(keys, storage.keys().unwrap());
+        assert_eq!(storage.keys_page(10, 5).unwrap(), keys[10..15]);
+        assert_eq!(storage.keys_page(248, 5).unwrap(), keys[248..]);
+    }
+
+    #[test]
+    fn test_iter_decrypted() {
+        let password = "password";
+        let db = TempDb::new();
+
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow("iter000", "testing123", None).is_ok());
+        assert!(storage
+            .stow("iter001", "testing123", Some(password))
+            .is_ok());
+
+        let vals: Vec<(String, String)> = storage
+            .iter_decrypted(password)
+            .unwrap()
+            .map(|e| e.unwrap())
+            .take(2)
+            .collect();
+        assert_eq!(
+            vals[1],
+            (String::from("iter001"), String::from("testing123"))
+        );
+
+        assert!(storage.stow("iterbad", "testing123", Some("other")).is_ok());
+        let failed = storage
+            .iter_decrypted(password)
+            .unwrap()
+            .filter(|e| e.is_err())
+            .count();
+        assert_eq!(failed, 1);
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        let long = "x".repeat(2000);
+
+        for key in ["", " \t\n", long.as_str()] {
+            assert!(matches!(
+                storage.stow(key, "testing123", None),
+                Err(depot::Error::InvalidKey(_))
+            ));
+        }
+
+        assert!(storage.stow("invalidkeys", "testing123", None).is_ok());
+        assert!(matches!(
+            storage.copy("invalidkeys", "", None),
+            Err(depot::Error::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_clear() {
+        let password = "password";
+        let db = TempDb::new();
+        let path = db.path();
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage.stow("clear1", "testing123", None).is_ok());
+        assert!(storage.stow("clear2", "testing123", Some(password)).is_ok());
+
+        assert_eq!(storage.clear().unwrap(), 2);
+        assert!(storage.keys().unwrap().is_empty());
+        assert!(storage.verify_password(password).unwrap());
+        assert!(!storage.verify_password("other").unwrap());
+    }
+
+    #[test]
+    fn test_stats() {
+        let db = TempDb::new();
+        let path = db.path();
+
+        let storage = depot::Depot::new(path).unwrap();
+        let empty = storage.stats().unwrap();
+        assert_eq!(empty.total, 0);
+        assert_eq!(empty.earliest, None);
+
+        assert!(storage.stow("stats1", "testing123", None).is_ok());
+        assert!(storage.stow("stats2", "testing123", None).is_ok());
+        assert!(storage
+            .stow("stats3", "testing123", Some("password"))
+            .is_ok());
+
+        let stats = storage.stats().unwrap();
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.encrypted, 1);
+        assert_eq!(stats.plaintext, 2);
+        assert!(stats.earliest.is_some() && stats.earliest <= stats.latest);
+        assert!(stats.file_size > 0);
+    }
+
+    #[test]
+    fn test_with_rng() {
+        use rand::SeedableRng;
+
+        let dbs = [TempDb::new(), TempDb::new()];
+        let vals: Vec<String> = dbs
+            .iter()
+            .map(|db| {
+                let path = db.path();
+                let rng = rand::rngs::StdRng::seed_from_u64(7);
+                let storage = depot::Depot::with_rng(path, Default::default(), rng).unwrap();
+                assert!(storage.stow("rng", "testing123", Some("password")).is_ok());
+                assert_eq!(
+                    storage.fetch("rng", Some("password")).unwrap(),
+                    "testing123"
+                );
+
+                let conn = rusqlite::Connection::open(path).unwrap();
+                conn.query_row("select val from storage where key = 'rng'", (), |row| {
+                    row.get(0)
+                })
+                .unwrap()
+            })
+            .collect();
+
+        assert_eq!(vals[0], vals[1]);
+    }
+
+    #[test]
+    fn test_named_stores() {
+        let db = TempDb::new();
+        let path = db.path();
+        let work = depot::Depot::new_named(path, "work").unwrap();
+        let home = depot::Depot::new_named(path, "home").unwrap();
+        assert_eq!(work.store(), "work");
+
+        assert!(work.stow("email", "work@example.com", None).is_ok());
+        assert!(home
+            .stow("email", "home@example.com", Some("password"))
+            .is_ok());
+        assert!(work.stow("vpn", "secret", Some("password")).is_ok());
+
+        assert_eq!(work.fetch("email", None).unwrap(), "work@example.com");
+        assert_eq!(
+            home.fetch("email", Some("password")).unwrap(),
+            "home@example.com"
+        );
+        assert_eq!(work.keys().unwrap(), vec!["email", "vpn"]);
+        assert_eq!(home.keys().unwrap(), vec!["email"]);
+        assert_eq!(work.stats().unwrap().total, 2);
+
+        let default = depot::Depot::new(path).unwrap();
+        assert!(default.keys().unwrap().is_empty());
+        assert!(matches!(
+            default.fetch("email", None),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_named_stores_clear() {
+        let db = TempDb::new();
+        let work = depot::Depot::new_named(db.path(), "work").unwrap();
+        let home = depot::Depot::new_named(db.path(), "home").unwrap();
+        assert!(work.stow("email", "work@example.com", None).is_ok());
+        assert!(work.stow("vpn", "secret", Some("password")).is_ok());
+        assert!(home.stow("email", "home@example.com", None).is_ok());
+
+        assert_eq!(home.clear().unwrap(), 1);
+        assert_eq!(work.keys().unwrap(), vec!["email", "vpn"]);
+        assert!(work.drop("email").unwrap());
+        assert!(!home.drop("vpn").unwrap());
+    }
+
+    #[test]
+    fn test_table_prefix() {
+        let db = TempDb::new();
+        let path = db.path();
+        let app = depot::Depot::with_table_prefix(path, "myapp_").unwrap();
+        assert!(app.stow("api", "token", Some("password")).is_ok());
+        assert!(app.stow("host", "example.com", None).is_ok());
+        assert_eq!(app.fetch("api", Some("password")).unwrap(), "token");
+
+        let conn = rusqlite::Connection::open(path).unwrap();
+        let tables: Vec<String> = conn
+            .prepare("select name from sqlite_master where type = 'table' order by name")
+            .unwrap()
+            .query_map((), |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        for table in ["myapp_storage", "myapp_salt", "myapp_meta", "myapp_history"] {
+            assert!(tables.iter().any(|t| t == table));
+        }
+        assert!(!tables.iter().any(|t| t == "storage"));
+    }
+
+    #[test]
+    fn test_table_prefix_isolated() {
+        let db = TempDb::new();
+        let path = db.path();
+        let app = depot::Depot::with_table_prefix(path, "myapp_").unwrap();
+        assert!(app.stow("api", "token", Some("password")).is_ok());
+        assert!(app.stow("host", "example.com", None).is_ok());
+
+        let default = depot::Depot::new(path).unwrap();
+        assert!(default.keys().unwrap().is_empty());
+        assert!(default.stow("host", "other.example.com", None).is_ok());
+        assert!(app.drop("host").unwrap());
+        assert_eq!(default.fetch("host", None).unwrap(), "other.example.com");
+        assert_eq!(app.keys().unwrap(), vec!["api"]);
+
+        let reopened = depot::Depot::open_with_options(
+            path,
+            depot::Options {
+                table_prefix: String::from("myapp_"),
+                ..depot::Options::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(reopened.fetch("api", Some("password")).unwrap(), "token");
+    }
+
+    #[test]
+    fn test_table_prefix_invalid() {
+        let db = TempDb::new();
+        assert!(depot::Depot::with_table_prefix(db.path(), "bad;drop").is_err());
+        assert!(depot::Depot::with_table_prefix(db.path(), "1app_").is_err());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_async_depot() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::AsyncDepot::new(depot::Depot::new(path).unwrap());
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            assert!(depot.stow("api", "token", Some("password")).await.is_ok());
+            assert!(depot.stow("host", "example.com", None).await.is_ok());
+            assert_eq!(depot.fetch("api", Some("password")).await.unwrap(), "token");
+            assert!(matches!(
+                depot.fetch("api", Some("wrong")).await,
+                Err(depot::Error::BadPassword)
+            ));
+            assert_eq!(depot.keys().await.unwrap(), vec!["api", "host"]);
+            assert!(depot.drop("host").await.unwrap());
+            assert!(!depot.drop("host").await.unwrap());
+        });
+        assert_eq!(depot.depot().keys().unwrap(), vec!["api"]);
+    }
+
+    #[test]
+    fn test_cache_keys() {
+        let db = TempDb::new();
+        let path = db.path();
+        let opts = depot::Options {
+            cache_keys: true,
+            ..depot::Options::default()
+        };
+        let depot = depot::Depot::with_options(path, opts).unwrap();
+        for i in 0..10 {
+            let key = format!("key{}", i);
+            assert!(depot.stow(&key, &key, Some("password")).is_ok());
+        }
+        assert!(depot.stow("custom", "value", Some("other")).is_ok());
+
+        assert_eq!(depot.fetch("key3", Some("password")).unwrap(), "key3");
+        assert!(matches!(
+            depot.fetch("key3", Some("wrong")),
+            Err(depot::Error::BadPassword)
+        ));
+        assert_eq!(depot.fetch("custom", Some("other")).unwrap(), "value");
+
+        assert_eq!(depot.change_password("password", "new").unwrap(), 10);
+        assert_eq!(depot.fetch("key7", Some("new")).unwrap(), "key7");
+        assert!(depot.fetch("key7", Some("password")).is_err());
+
+        depot.clear_key_cache();
+        assert_eq!(depot.fetch("key9", Some("new")).unwrap(), "key9");
+    }
+
+    #[test]
+    fn test_fetch_or() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new(path).unwrap();
+        assert!(depot.stow("host", "example.com", None).is_ok());
+        assert!(depot.stow("token", "secret", Some("password")).is_ok());
+
+        assert_eq!(
+            depot.fetch_or("host", None, "localhost").unwrap(),
+            "example.com"
+        );
+        assert_eq!(depot.fetch_or("port", None, "8080").unwrap(), "8080");
+        assert_eq!(
+            depot.fetch_or("token", Some("password"), "none").unwrap(),
+            "secret"
+        );
+        assert!(matches!(
+            depot.fetch_or("token", None, "none"),
+            Err(depot::Error::NeedPassword)
+        ));
+        assert!(matches!(
+            depot.fetch_or("token", Some("wrong"), "none"),
+            Err(depot::Error::BadPassword)
+        ));
+    }
+
+    #[test]
+    fn test_drop_matching() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new(path).unwrap();
+        for key in ["old/a", "old/b", "old/c/d", "new/a", "older"] {
+            assert!(depot.stow(key, "value", None).is_ok());
+        }
+
+        assert_eq!(depot.drop_matching("old/*").unwrap(), 3);
+        assert_eq!(depot.keys().unwrap(), vec!["new/a", "older"]);
+        assert_eq!(depot.drop_matching("old/*").unwrap(), 0);
+        assert_eq!(depot.drop_matching("?ew/[ab]").unwrap(), 1);
+        assert_eq!(depot.keys().unwrap(), vec!["older"]);
+    }
+
+    #[test]
+    fn test_drop_matching_soft_delete() {
+        let db = TempDb::new();
+        let path = db.path();
+        let opts = depot::Options {
+            soft_delete: true,
+            ..depot::Options::default()
+        };
+        let depot = depot::Depot::with_options(path, opts).unwrap();
+        assert!(depot.stow("tmp/a", "value", None).is_ok());
+        assert!(depot.stow("tmp/b", "value", None).is_ok());
+        assert_eq!(depot.drop_matching("tmp/*").unwrap(), 2);
+        assert!(depot.keys().unwrap().is_empty());
+        assert!(depot.undelete("tmp/b").is_ok());
+        assert_eq!(depot.keys().unwrap(), vec!["tmp/b"]);
+    }
+
+    #[test]
+    fn test_is_encrypted() {
+        let db = TempDb::new();
+        let path = db.path();
+        let opts = depot::Options {
+            compress: true,
+            ..depot::Options::default()
+        };
+        let depot = depot::Depot::with_options(path, opts).unwrap();
+        assert!(depot.stow("plain", "value", None).is_ok());
+        assert!(depot.stow("secret", "value", Some("password")).is_ok());
+
+        assert!(!depot.is_encrypted("plain").unwrap());
+        assert!(depot.is_encrypted("secret").unwrap());
+        assert!(matches!(
+            depot.is_encrypted("missing"),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_encrypt_entry() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new(path).unwrap();
+        assert!(depot.stow("master", "first", Some("password")).is_ok());
+        assert!(depot.stow("note", "line one\nline two", None).is_ok());
+        let modified = depot.metadata("note").unwrap().modified;
+
+        assert!(depot.encrypt_entry("note", "password").is_ok());
+        assert!(depot.is_encrypted("note").unwrap());
+        assert!(!depot.has_custom_password("note").unwrap());
+        assert_eq!(depot.metadata("note").unwrap().modified, modified);
+        assert!(matches!(
+            depot.fetch("note", None),
+            Err(depot::Error::NeedPassword)
+        ));
+        assert_eq!(
+            depot.fetch("note", Some("password")).unwrap(),
+            "line one\nline two"
+        );
+        assert!(depot.encrypt_entry("note", "password").is_err());
+    }
+
+    #[test]
+    fn test_decrypt_entry() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot
+            .stow("note", "line one\nline two", Some("password"))
+            .is_ok());
+        let modified = depot.metadata("note").unwrap().modified;
+
+        assert!(matches!(
+            depot.decrypt_entry("note", "wrong"),
+            Err(depot::Error::BadPassword)
+        ));
+        assert!(depot.decrypt_entry("note", "password").is_ok());
+        assert!(!depot.is_encrypted("note").unwrap());
+        assert_eq!(depot.fetch("note", None).unwrap(), "line one\nline two");
+        assert_eq!(depot.metadata("note").unwrap().modified, modified);
+        assert!(depot.decrypt_entry("note", "password").is_err());
+    }
+
+    #[test]
+    fn test_encrypt_entry_custom_password() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("master", "first", Some("password")).is_ok());
+        assert!(depot.stow("note", "line one\nline two", None).is_ok());
+
+        assert!(depot.encrypt_entry("note", "other").is_ok());
+        assert!(depot.has_custom_password("note").unwrap());
+        assert!(matches!(
+            depot.encrypt_entry("missing", "password"),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_encrypt_entry_compressed() {
+        let db = TempDb::new();
+        let opts = depot::Options {
+            compress: true,
+            ..depot::Options::default()
+        };
+        let depot = depot::Depot::with_options(db.path(), opts).unwrap();
+        let val = "compressible ".repeat(100);
+        assert!(depot.stow("big", &val, None).is_ok());
+        assert!(depot.encrypt_entry("big", "password").is_ok());
+        assert_eq!(depot.fetch("big", Some("password")).unwrap(), val);
+        assert!(depot.decrypt_entry("big", "password").is_ok());
+        assert_eq!(depot.fetch("big", None).unwrap(), val);
+    }
+
+    #[test]
+    fn test_max_value_len() {
+        let db = TempDb::new();
+        let path = db.path();
+        let opts = depot::Options {
+            max_value_len: 16,
+            ..depot::Options::default()
+        };
+        let depot = depot::Depot::with_options(path, opts).unwrap();
+        assert!(depot.stow("fits", &"x".repeat(16), None).is_ok());
+        assert!(matches!(
+            depot.stow("big", &"x".repeat(17), None),
+            Err(depot::Error::TooLarge(_))
+        ));
+        assert!(matches!(
+            depot.stow("big", &"x".repeat(17), Some("password")),
+            Err(depot::Error::TooLarge(_))
+        ));
+        assert!(matches!(
+            depot.fetch("big", None),
+            Err(depot::Error::NotFound(_))
+        ));
+
+        let depot = depot::Depot::new(path).unwrap();
+        assert!(depot.stow("big", &"x".repeat(17), None).is_ok());
+        assert!(matches!(
+            depot.stow("huge", &"x".repeat(5 * 1024 * 1024), None),
+            Err(depot::Error::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_password_strength() {
+        use depot::{password_strength, Strength};
+        assert_eq!(password_strength(""), Strength::Weak);
+        assert_eq!(password_strength("password"), Strength::Weak);
+        assert_eq!(
+            password_strength("aaaaaaaaaaaaaaaaaaaaaaaa"),
+            Strength::Weak
+        );
+        assert_eq!(
+            password_strength("abcdefghijklmnopqrstuvwx"),
+            Strength::Weak
+        );
+        assert_eq!(password_strength("12345678987654321"), Strength::Weak);
+        assert_eq!(password_strength("mango42tree"), Strength::Fair);
+        assert_eq!(password_strength("k#9Vq!2mZ@7pLx$4"), Strength::Strong);
+        assert_eq!(
+            password_strength("correct horse battery staple"),
+            Strength::Strong
+        );
+        assert!(Strength::Weak < Strength::Fair && Strength::Fair < Strength::Strong);
+    }
+
+    #[test]
+    fn test_open_path() {
+        use std::os::unix::ffi::OsStrExt;
+        let db = TempDb::with_suffix(OsStr::from_bytes(b"_\xff.db"));
+        let path = db.0.as_path();
+        let depot = depot::Depot::open_path(path).unwrap();
+        assert!(depot.stow("key", "value", None).is_ok());
+        drop(depot);
+
+        let depot = depot::Depot::open_path(path).unwrap();
+        assert_eq!(depot.fetch("key", None).unwrap(), "value");
+        assert!(path.is_file());
+    }
+
+    #[test]
+    fn test_error_code() {
+        use depot::ErrorCode;
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new(path).unwrap();
+        assert!(depot.stow("secret", "value", Some("password")).is_ok());
+
+        let code = |r: depot::Result<String>| r.unwrap_err().code();
+        assert_eq!(code(depot.fetch("missing", None)), ErrorCode::NotFound);
+        assert_eq!(code(depot.fetch("secret", None)), ErrorCode::NeedPassword);
+        assert_eq!(
+            code(depot.fetch("secret", Some("wrong"))),
+            ErrorCode::BadPassword
+        );
+        assert_eq!(
+            depot.stow("", "value", None).unwrap_err().code(),
+            ErrorCode::Other
+        );
+        assert_eq!(
+            depot::Depot::open(TempDb::new().path())
+                .err()
+                .unwrap()
+                .code(),
+            ErrorCode::NotFound
+        );
+    }
+
+    #[test]
+    fn test_stow_with_modified() {
+        let (src_db, dst_db) = (TempDb::new(), TempDb::new());
+        let src = depot::Depot::new(src_db.path()).unwrap();
+        assert!(src
+            .stow_with_modified("old", "value", None, 1_000_000_000)
+            .is_ok());
+        assert!(src
+            .stow_with_modified("secret", "value", Some("password"), 1_500_000_000)
+            .is_ok());
+        assert_eq!(src.metadata("old").unwrap().modified, Some(1_000_000_000));
+
+        let dst = depot::Depot::new(dst_db.path()).unwrap();
+        assert!(dst.stow("old", "stale", None).is_ok());
+        assert_eq!(src.copy_to(&dst, Some("password")).unwrap(), 2);
+        assert_eq!(dst.fetch("old", None).unwrap(), "value");
+        assert_eq!(dst.metadata("old").unwrap().modified, Some(1_000_000_000));
+        assert_eq!(
+            dst.metadata("secret").unwrap().modified,
+            Some(1_500_000_000)
+        );
+    }
+
+    #[test]
+    fn test_merge_keep_newer() {
+        let (src_db, dst_db) = (TempDb::new(), TempDb::new());
+        let src = depot::Depot::new(src_db.path()).unwrap();
+        let dst = depot::Depot::new(dst_db.path()).unwrap();
+        assert!(dst
+            .stow_with_modified("old", "value", None, 1_000_000_000)
+            .is_ok());
+        assert!(dst
+            .stow_with_modified("secret", "value", Some("password"), 1_500_000_000)
+            .is_ok());
+        assert!(src
+            .stow_with_modified("secret", "older", Some("password"), 1_000_000_000)
+            .is_ok());
+
+        assert!(src.stow("old", "newer", None).is_ok());
+        let report = dst
+            .merge(&src, Some("password"), depot::Conflict::KeepNewer)
+            .unwrap();
+        assert_eq!(report.updated, vec!["old"]);
+        assert_eq!(report.skipped, vec!["secret"]);
+        assert!(dst.metadata("old").unwrap().modified > Some(1_500_000_000));
+    }
+
+    #[test]
+    fn test_dry_run_drop() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("old/a", "a", None).is_ok());
+        assert!(depot.stow("old/b", "b", None).is_ok());
+        assert!(depot.stow("keep", "c", None).is_ok());
+
+        let opts = depot::Options {
+            dry_run: true,
+            ..depot::Options::default()
+        };
+        let dry = depot::Depot::with_options(db.path(), opts).unwrap();
+        assert_eq!(dry.keys_matching("old/*").unwrap(), vec!["old/a", "old/b"]);
+        assert_eq!(dry.drop_matching("old/*").unwrap(), 2);
+        assert!(dry.drop("keep").unwrap());
+        assert!(!dry.drop("missing").unwrap());
+        assert_eq!(depot.keys().unwrap(), vec!["keep", "old/a", "old/b"]);
+    }
+
+    #[test]
+    fn test_dry_run_prune() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow_expiring("gone", "a", None, Some(1)).is_ok());
+
+        let opts = depot::Options {
+            dry_run: true,
+            ..depot::Options::default()
+        };
+        let dry = depot::Depot::with_options(db.path(), opts).unwrap();
+        assert_eq!(dry.expired_keys().unwrap(), vec!["gone"]);
+        assert_eq!(dry.prune_expired().unwrap(), 1);
+        assert_eq!(depot.expired_keys().unwrap(), vec!["gone"]);
+    }
+
+    #[test]
+    fn test_dry_run_change_password() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("secret", "a", Some("password")).is_ok());
+
+        let opts = depot::Options {
+            dry_run: true,
+            ..depot::Options::default()
+        };
+        let dry = depot::Depot::with_options(db.path(), opts).unwrap();
+        assert_eq!(dry.change_password("password", "new").unwrap(), 1);
+        assert!(matches!(
+            dry.change_password("wrong", "new"),
+            Err(depot::Error::BadPassword)
+        ));
+        assert_eq!(depot.fetch("secret", Some("password")).unwrap(), "a");
+        assert!(depot.verify_password("password").unwrap());
+    }
+
+    #[test]
+    fn test_dry_run_clear() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("a", "a", None).is_ok());
+        assert!(depot.stow("b", "b", Some("password")).is_ok());
+
+        let opts = depot::Options {
+            dry_run: true,
+            ..depot::Options::default()
+        };
+        let dry = depot::Depot::with_options(db.path(), opts).unwrap();
+        assert_eq!(dry.clear().unwrap(), 2);
+        assert_eq!(depot.keys().unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_dry_run_import() {
+        let (db, other_db) = (TempDb::new(), TempDb::new());
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("a", "a", None).is_ok());
+        assert!(depot.stow("b", "b", Some("password")).is_ok());
+
+        let opts = depot::Options {
+            dry_run: true,
+            ..depot::Options::default()
+        };
+        let other = depot::Depot::with_options(other_db.path(), opts).unwrap();
+        let export = depot.export_encrypted().unwrap();
+        assert_eq!(other.import_encrypted(&export).unwrap(), 2);
+        assert!(other.keys().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_drop_checkpoints() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new(path).unwrap();
+        let other = depot::Depot::new(path).unwrap();
+        assert!(depot.stow("key", "val", Some("password")).is_ok());
+        assert!(std::fs::metadata(format!("{}-wal", path)).unwrap().len() > 0);
+
+        drop(depot);
+        assert_eq!(std::fs::metadata(format!("{}-wal", path)).unwrap().len(), 0);
+        assert_eq!(other.fetch("key", Some("password")).unwrap(), "val");
+    }
+
+    #[test]
+    fn test_fetch_raw() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new(path).unwrap();
+        assert!(depot.stow("plain", "val", None).is_ok());
+        assert!(depot.stow("secret", "val", Some("password")).is_ok());
+
+        let raw = depot.fetch_raw("plain").unwrap();
+        assert_eq!(raw.val, "val");
+        assert_eq!(raw.nonce, None);
+        assert!(!raw.encrypted);
+        assert!(!raw.compressed);
+        assert!(!raw.custom);
+
+        let raw = depot.fetch_raw("secret").unwrap();
+        assert_ne!(raw.val, "val");
+        assert_eq!(raw.nonce.unwrap().len(), 12);
+        assert!(raw.encrypted);
+        assert!(raw.bound);
+        assert_eq!(raw.kdf, depot::KdfHash::Sha256);
+        assert_eq!(raw.iterations, depot::KDF_ITERATIONS);
+
+        assert!(depot.stow("custom", "val", Some("other")).is_ok());
+        assert!(depot.fetch_raw("custom").unwrap().custom);
+
+        assert!(matches!(
+            depot.fetch_raw("missing"),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_stow_raw() {
+        let db = TempDb::new();
+        let opts = depot::Options {
+            compress: true,
+            ..depot::Options::default()
+        };
+        let depot = depot::Depot::with_options(db.path(), opts).unwrap();
+        assert!(depot.stow("secret", "val", Some("password")).is_ok());
+        assert!(depot.stow("custom", "val", Some("other")).is_ok());
+        assert!(depot.stow("packed", &"x".repeat(1000), None).is_ok());
+        assert!(depot.stow("plain", "val", None).is_ok());
+
+        for key in ["secret", "custom", "packed", "plain"] {
+            let raw = depot.fetch_raw(key).unwrap();
+            assert!(depot.drop(key).unwrap());
+            assert!(depot.stow_raw(key, &raw).is_ok());
+            assert_eq!(depot.fetch_raw(key).unwrap(), raw);
+        }
+        assert_eq!(depot.fetch("secret", Some("password")).unwrap(), "val");
+        assert_eq!(depot.fetch("custom", Some("other")).unwrap(), "val");
+        assert!(depot.has_custom_password("custom").unwrap());
+        assert_eq!(depot.fetch("packed", None).unwrap(), "x".repeat(1000));
+        assert_eq!(depot.fetch("plain", None).unwrap(), "val");
+    }
+
+    #[test]
+    fn test_stow_raw_kdf() {
+        let db = TempDb::new();
+        let legacy = depot::Options {
+            kdf_hash: depot::KdfHash::Sha1,
+            kdf_iterations: 1000,
+            ..Default::default()
+        };
+        let depot = depot::Depot::with_options(db.path(), legacy).unwrap();
+        assert!(depot.stow("secret", "val", Some("password")).is_ok());
+        let raw = depot.fetch_raw("secret").unwrap();
+        assert!(depot.drop("secret").unwrap());
+        drop(depot);
+
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow_raw("secret", &raw).is_ok());
+        assert_eq!(depot.fetch_raw("secret").unwrap(), raw);
+        assert_eq!(depot.fetch("secret", Some("password")).unwrap(), "val");
+    }
+
+    #[test]
+    fn test_stow_raw_invalid() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("secret", "val", Some("password")).is_ok());
+        let raw = depot.fetch_raw("secret").unwrap();
+
+        let bad = depot::RawEntry {
+            nonce: Some(vec![0; 8]),
+            ..raw.clone()
+        };
+        assert_eq!(
+            depot.stow_raw("bad", &bad).unwrap_err().to_string(),
+            "the nonce for bad is 8 bytes rather than 12"
+        );
+        let bad = depot::RawEntry {
+            val: String::from("not base64!"),
+            ..raw.clone()
+        };
+        assert!(depot
+            .stow_raw("bad", &bad)
+            .unwrap_err()
+            .to_string()
+            .starts_with("the ciphertext for bad is not valid base64"));
+        let bad = depot::RawEntry {
+            nonce: None,
+            ..raw.clone()
+        };
+        assert!(depot.stow_raw("bad", &bad).is_err());
+        let bad = depot::RawEntry {
+            nonce: None,
+            encrypted: false,
+            ..raw.clone()
+        };
+        assert!(depot.stow_raw("bad", &bad).is_err());
+        let bad = depot::RawEntry {
+            iterations: 0,
+            ..raw.clone()
+        };
+        assert!(depot.stow_raw("bad", &bad).is_err());
+        let bad = depot::RawEntry {
+            val: String::from("dmFs"),
+            nonce: None,
+            encrypted: false,
+            compressed: true,
+            bound: false,
+            ..raw
+        };
+        assert!(depot.stow_raw("bad", &bad).is_err());
+        assert!(matches!(
+            depot.fetch_raw("bad"),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_keys_filtered() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new(path).unwrap();
+        assert!(depot.stow("b", "val", Some("password")).is_ok());
+        assert!(depot.stow("a", "val", None).is_ok());
+        assert!(depot.stow("c", "val", None).is_ok());
+        assert!(depot.stow("d", "val", Some("password")).is_ok());
+
+        assert_eq!(depot.keys_filtered(Some(true)).unwrap(), vec!["b", "d"]);
+        assert_eq!(depot.keys_filtered(Some(false)).unwrap(), vec!["a", "c"]);
+        assert_eq!(depot.keys_filtered(None).unwrap(), depot.keys().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "sqlcipher")]
+    fn test_new_encrypted() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new_encrypted(path, "db password").unwrap();
+        assert!(depot.stow("plain", "visible", None).is_ok());
+        assert!(depot.stow("secret", "hidden", Some("password")).is_ok());
+        drop(depot);
+
+        let file = std::fs::read(path).unwrap();
+        assert!(!file.windows(7).any(|w| w == b"visible"));
+        assert!(!file.windows(6).any(|w| w == b"secret"));
+        assert!(matches!(
+            depot::Depot::new_encrypted(path, "wrong"),
+            Err(depot::Error::BadPassword)
+        ));
+        assert!(depot::Depot::new(path).is_err());
+
+        let depot = depot::Depot::new_encrypted(path, "db password").unwrap();
+        assert_eq!(depot.fetch("plain", None).unwrap(), "visible");
+        assert_eq!(depot.fetch("secret", Some("password")).unwrap(), "hidden");
+    }
+
+    #[test]
+    fn test_unlock() {
+        let db = TempDb::new();
+        let path = db.path();
+        let mut depot = depot::Depot::new(path).unwrap();
+        assert!(depot.stow("secret", "val", Some("password")).is_ok());
+        assert!(depot.stow("custom", "val", Some("other")).is_ok());
+        assert!(depot.stow("plain", "val", None).is_ok());
+
+        assert!(matches!(
+            depot.unlock("wrong"),
+            Err(depot::Error::BadPassword)
+        ));
+        assert!(!depot.is_unlocked());
+        assert!(depot.unlock("password").is_ok());
+        assert!(depot.is_unlocked());
+
+        assert_eq!(depot.fetch("secret", None).unwrap(), "val");
+        assert_eq!(depot.fetch("plain", None).unwrap(), "val");
+        assert!(matches!(
+            depot.fetch("custom", None),
+            Err(depot::Error::NeedPassword)
+        ));
+        assert_eq!(depot.fetch("custom", Some("other")).unwrap(), "val");
+    }
+
+    #[test]
+    fn test_unlocked_stow() {
+        let db = TempDb::new();
+        let mut depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("secret", "val", Some("password")).is_ok());
+        assert!(depot.stow("plain", "val", None).is_ok());
+        assert!(depot.unlock("password").is_ok());
+
+        assert!(depot.stow("secret", "new", None).is_ok());
+        assert!(depot.is_encrypted("secret").unwrap());
+        assert!(depot.stow("plain", "new", None).is_ok());
+        assert!(!depot.is_encrypted("plain").unwrap());
+        assert!(depot.copy("secret", "copied", None).is_ok());
+        assert_eq!(depot.fetch("copied", None).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_lock() {
+        let db = TempDb::new();
+        let mut depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("secret", "new", Some("password")).is_ok());
+        assert!(depot.unlock("password").is_ok());
+
+        depot.lock();
+        assert!(!depot.is_unlocked());
+        assert!(matches!(
+            depot.fetch("secret", None),
+            Err(depot::Error::NeedPassword)
+        ));
+        assert_eq!(depot.fetch("secret", Some("password")).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_salt_len() {
+        let db = TempDb::new();
+        let path = db.path();
+        let salt_len = |path: &str| -> usize {
+            let conn = rusqlite::Connection::open(path).unwrap();
+            conn.query_row("select length(data) from salt", (), |row| row.get(0))
+                .unwrap()
+        };
+
+        let opts = depot::Options {
+            salt_len: 8,
+            ..depot::Options::default()
+        };
+        assert!(depot::Depot::with_options(path, opts).is_err());
+
+        let opts = depot::Options {
+            salt_len: depot::MIN_SALT_LEN,
+            ..depot::Options::default()
+        };
+        let depot = depot::Depot::with_options(path, opts).unwrap();
+        assert!(depot.stow("key", "val", Some("password")).is_ok());
+        drop(depot);
+        assert_eq!(salt_len(path), 16);
+
+        let depot = depot::Depot::new(path).unwrap();
+        assert_eq!(depot.fetch("key", Some("password")).unwrap(), "val");
+        assert!(depot.rotate_salt("password").is_ok());
+        assert_eq!(depot.fetch("key", Some("password")).unwrap(), "val");
+        drop(depot);
+        assert_eq!(salt_len(path), depot::SALT_LEN);
+    }
+
+    #[test]
+    fn test_hex_encoding() {
+        let db = TempDb::new();
+        let path = db.path();
+        let opts = depot::Options {
+            encoding: depot::Encoding::Hex,
+            compress: true,
+            ..depot::Options::default()
+        };
+        let depot = depot::Depot::with_options(path, opts).unwrap();
+        assert!(depot.stow("secret", "val", Some("password")).is_ok());
+        assert!(depot.stow("packed", &"x".repeat(1000), None).is_ok());
+        drop(depot);
+
+        let depot = depot::Depot::new(path).unwrap();
+        for key in ["secret", "packed"] {
+            let raw = depot.fetch_raw(key).unwrap();
+            assert!(raw.val.bytes().all(|b| b.is_ascii_hexdigit()));
+        }
+        assert_eq!(depot.fetch("secret", Some("password")).unwrap(), "val");
+        assert_eq!(depot.fetch("packed", None).unwrap(), "x".repeat(1000));
+        assert!(depot.stow("secret", "new", Some("password")).is_ok());
+        assert!(depot
+            .fetch_raw("secret")
+            .unwrap()
+            .val
+            .bytes()
+            .all(|b| b.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_hex_encoding_import() {
+        let (db, other_db) = (TempDb::new(), TempDb::new());
+        let opts = depot::Options {
+            encoding: depot::Encoding::Hex,
+            compress: true,
+            ..depot::Options::default()
+        };
+        let depot = depot::Depot::with_options(db.path(), opts).unwrap();
+        assert!(depot.stow("secret", "new", Some("password")).is_ok());
+        assert!(depot.stow("packed", &"x".repeat(1000), None).is_ok());
+
+        let other = depot::Depot::new(other_db.path()).unwrap();
+        assert_eq!(
+            other
+                .import_encrypted(&depot.export_encrypted().unwrap())
+                .unwrap(),
+            2
+        );
+        assert_eq!(other.fetch("secret", Some("password")).unwrap(), "new");
+        assert_eq!(other.fetch("packed", None).unwrap(), "x".repeat(1000));
+        assert_ne!(
+            other.fetch_raw("secret").unwrap().val,
+            depot.fetch_raw("secret").unwrap().val
+        );
+    }
+
+    #[test]
+    fn test_hex_encoding_malformed() {
+        let db = TempDb::new();
+        let opts = depot::Options {
+            encoding: depot::Encoding::Hex,
+            ..depot::Options::default()
+        };
+        let depot = depot::Depot::with_options(db.path(), opts).unwrap();
+        assert!(depot.stow("secret", "val", Some("password")).is_ok());
+
+        let conn = rusqlite::Connection::open(db.path()).unwrap();
+        conn.execute("update storage set val = 'abc' where key = 'secret'", ())
+            .unwrap();
+        assert!(matches!(
+            depot.fetch("secret", Some("password")),
+            Err(depot::Error::Malformed(k, _)) if k == "secret"
+        ));
+    }
+
+    #[test]
+    fn test_count() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new(path).unwrap();
+        assert_eq!(depot.count(None).unwrap(), 0);
+        assert!(depot.stow("work/a", "val", None).is_ok());
+        assert!(depot.stow("work/b", "val", Some("password")).is_ok());
+        assert!(depot.stow("home/c", "val", None).is_ok());
+
+        assert_eq!(depot.count(None).unwrap(), 3);
+        assert_eq!(depot.count(Some("work/*")).unwrap(), 2);
+        assert_eq!(depot.count(Some("other/*")).unwrap(), 0);
+        assert!(depot.drop("work/a").unwrap());
+        assert_eq!(depot.count(Some("work/*")).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_tags() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("bank", "val", Some("password")).is_ok());
+        assert!(depot.stow("mail", "val", None).is_ok());
+        assert!(depot.stow("wifi", "val", None).is_ok());
+
+        assert!(depot.tag("bank", &["work", "money"]).is_ok());
+        assert!(depot.tag("mail", &["work"]).is_ok());
+        assert!(depot.tag("mail", &["work"]).is_ok());
+        assert_eq!(depot.tags("bank").unwrap(), ["money", "work"]);
+        assert_eq!(depot.tags("mail").unwrap(), ["work"]);
+        assert!(depot.tags("wifi").unwrap().is_empty());
+        assert_eq!(depot.keys_by_tag("work").unwrap(), ["bank", "mail"]);
+        assert_eq!(depot.keys_by_tag("money").unwrap(), ["bank"]);
+        assert!(depot.keys_by_tag("wor").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tag_invalid() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("wifi", "val", None).is_ok());
+
+        assert!(depot.tag("wifi", &[""]).is_err());
+        assert!(depot.tag("wifi", &["a,b"]).is_err());
+        assert!(matches!(
+            depot.tag("missing", &["work"]),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_untag() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("bank", "val", Some("password")).is_ok());
+        assert!(depot.stow("mail", "val", None).is_ok());
+        assert!(depot.tag("bank", &["work", "money"]).is_ok());
+        assert!(depot.tag("mail", &["work"]).is_ok());
+
+        assert!(depot.stow("bank", "new", Some("password")).is_ok());
+        assert_eq!(depot.tags("bank").unwrap(), ["money", "work"]);
+        assert!(depot.untag("bank", &["work"]).is_ok());
+        assert_eq!(depot.keys_by_tag("work").unwrap(), ["mail"]);
+        assert!(depot.untag("mail", &["work"]).is_ok());
+        assert!(depot.keys_by_tag("work").unwrap().is_empty());
+        assert!(depot.drop("bank").unwrap());
+        assert!(depot.keys_by_tag("money").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tags_trash() {
+        let db = TempDb::new();
+        let depot = depot::Depot::with_options(
+            db.path(),
+            depot::Options {
+                soft_delete: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(depot.stow("a", "val", None).is_ok());
+        assert!(depot.stow("b", "val", None).is_ok());
+        assert!(depot.tag("a", &["work"]).is_ok());
+        assert!(depot.tag("b", &["work"]).is_ok());
+        assert!(depot.drop("a").unwrap());
+        assert!(depot.drop("b").unwrap());
+        assert!(depot.keys_by_tag("work").unwrap().is_empty());
+        assert!(depot.undelete("a").is_ok());
+        assert!(depot.stow("b", "new", None).is_ok());
+        assert_eq!(depot.keys_by_tag("work").unwrap(), ["a"]);
+    }
+
+    #[test]
+    fn test_undo_stow() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert_eq!(depot.undo().unwrap(), None);
+
+        assert!(depot.stow("undo1", "first", Some("password")).is_ok());
+        assert!(depot.tag("undo1", &["work"]).is_ok());
+        assert!(depot.stow("undo1", "second", None).is_ok());
+        assert_eq!(depot.undo().unwrap().as_deref(), Some("undo1"));
+        assert_eq!(depot.fetch("undo1", Some("password")).unwrap(), "first");
+        assert_eq!(depot.tags("undo1").unwrap(), ["work"]);
+        assert_eq!(depot.undo().unwrap(), None);
+    }
+
+    #[test]
+    fn test_undo_drop() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("undo1", "first", Some("password")).is_ok());
+
+        assert!(depot.drop("undo1").unwrap());
+        assert!(!depot.drop("missing").unwrap());
+        assert_eq!(depot.undo().unwrap().as_deref(), Some("undo1"));
+        assert_eq!(depot.fetch("undo1", Some("password")).unwrap(), "first");
+    }
+
+    #[test]
+    fn test_undo_reopened() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("undo2", "new", None).is_ok());
+
+        let reopened = depot::Depot::open(db.path()).unwrap();
+        assert_eq!(reopened.undo().unwrap().as_deref(), Some("undo2"));
+        assert!(matches!(
+            depot.fetch("undo2", None),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_undo_after_bulk() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("undo1", "first", Some("password")).is_ok());
+
+        assert!(depot.stow("undo2", "new", None).is_ok());
+        assert!(depot.change_password("password", "other").is_ok());
+        assert_eq!(depot.undo().unwrap(), None);
+        assert!(depot.stow("undo2", "newer", None).is_ok());
+        assert_eq!(depot.drop_matching("undo*").unwrap(), 2);
+        assert_eq!(depot.undo().unwrap(), None);
+    }
+
+    #[test]
+    fn test_stow_new() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::with_options(
+            path,
+            depot::Options {
+                soft_delete: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(depot.stow_new("new1", "first", None).is_ok());
+        assert!(matches!(
+            depot.stow_new("new1", "second", Some("password")),
+            Err(depot::Error::AlreadyExists(k)) if k == "new1"
+        ));
+        assert_eq!(depot.fetch("new1", None).unwrap(), "first");
+        assert!(!depot.is_encrypted("new1").unwrap());
+
+        assert!(depot.drop("new1").unwrap());
+        assert!(depot.stow_new("new1", "second", None).is_ok());
+        assert_eq!(depot.fetch("new1", None).unwrap(), "second");
+        assert!(depot.stow("new1", "third", None).is_ok());
+        assert_eq!(depot.fetch("new1", None).unwrap(), "third");
+    }
+
+    #[test]
+    fn test_fetch_with_meta() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new(path).unwrap();
+        assert!(depot
+            .stow_with_modified("meta1", "plain", None, 1000)
+            .is_ok());
+        assert!(depot
+            .stow_expiring("meta2", "secret", Some("password"), Some(i64::MAX))
+            .is_ok());
+
+        let (val, meta) = depot.fetch_with_meta("meta1", None).unwrap();
+        assert_eq!(val, "plain");
+        assert_eq!(
+            meta,
+            depot::EntryMeta {
+                modified: Some(1000),
+                expires: None,
+                encrypted: false,
+            }
+        );
+        let (val, meta) = depot.fetch_with_meta("meta2", Some("password")).unwrap();
+        assert_eq!(val, "secret");
+        assert_eq!(meta.expires, Some(i64::MAX));
+        assert!(meta.encrypted);
+        assert_eq!(meta.modified, depot.metadata("meta2").unwrap().modified);
+    }
+
+    #[test]
+    fn test_fetch_with_meta_errors() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("meta2", "secret", Some("password")).is_ok());
+
+        assert!(matches!(
+            depot.fetch_with_meta("meta2", None),
+            Err(depot::Error::NeedPassword)
+        ));
+        assert!(matches!(
+            depot.fetch_with_meta("missing", None),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_upgrade_kdf() {
+        let db = TempDb::new();
+        let path = db.path();
+        let depot = depot::Depot::new(path).unwrap();
+        assert!(depot.stow("kdf1", "first", Some("password")).is_ok());
+        assert!(depot.stow("kdf2", "second", Some("password")).is_ok());
+        assert!(depot.stow("kdf3", "custom", Some("other")).is_ok());
+        assert!(depot.stow("kdf4", "plain", None).is_ok());
+
+        assert!(matches!(
+            depot.upgrade_kdf("wrong", 8192),
+            Err(depot::Error::BadPassword)
+        ));
+        assert!(depot.upgrade_kdf("password", 0).is_err());
+        assert_eq!(depot.upgrade_kdf("password", 8192).unwrap(), 2);
+        assert_eq!(depot.upgrade_kdf("password", 8192).unwrap(), 0);
+        assert_eq!(depot.upgrade_kdf("password", 4096).unwrap(), 0);
+
+        assert_eq!(depot.fetch("kdf1", Some("password")).unwrap(), "first");
+        assert_eq!(depot.fetch("kdf2", Some("password")).unwrap(), "second");
+        assert_eq!(depot.fetch("kdf3", Some("other")).unwrap(), "custom");
+        assert!(depot.verify_password("password").unwrap());
+    }
+
+    #[test]
+    fn test_upgrade_kdf_hash() {
+        let db = TempDb::new();
+        let depot = depot::Depot::new(db.path()).unwrap();
+        assert!(depot.stow("kdf1", "first", Some("password")).is_ok());
+        assert!(depot.stow("kdf2", "second", Some("password")).is_ok());
+        assert_eq!(depot.upgrade_kdf("password", 8192).unwrap(), 2);
+
+        let depot = depot::Depot::open_with_options(
+            db.path(),
+            depot::Options {
+                kdf_hash: depot::KdfHash::Sha1,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(depot.upgrade_kdf("password", 8192).unwrap(), 2);
+        assert_eq!(depot.fetch("kdf1", Some("password")).unwrap(), "first");
+    }
+
+    #[test]
+    fn test_copy_to() {
+        let (src_db, dst_db) = (TempDb::new(), TempDb::new());
+        let src = depot::Depot::new(src_db.path()).unwrap();
+        let dst = depot::Depot::new(dst_db.path()).unwrap();
+
+        assert!(src.stow("copy1", "plain", None).is_ok());
+        assert!(src.stow("copy2", "secret", Some("password")).is_ok());
+        assert!(dst.stow("copy1", "old", None).is_ok());
+
+        assert!(matches!(
+            src.copy_to(&dst, None),
+            Err(depot::Error::NeedPassword)
+        ));
+        assert_eq!(dst.fetch("copy1", None).unwrap(), "old");
+
+        assert_eq!(src.copy_to(&dst, Some("password")).unwrap(), 2);
+        assert_eq!(dst.fetch("copy1", None).unwrap(), "plain");
+        assert!(matches!(
+            dst.fetch("copy2", None),
+            Err(depot::Error::NeedPassword)
+        ));
+        assert_eq!(dst.fetch("copy2", Some("password")).unwrap(), "secret");
+    }
+
+    #[test]
+    fn test_merge() {
+        let (ours_db, theirs_db) = (TempDb::new(), TempDb::new());
+        let ours = depot::Depot::new(ours_db.path()).unwrap();
+        let theirs = depot::Depot::new(theirs_db.path()).unwrap();
+
+        assert!(ours.stow("both", "ours", None).is_ok());
+        assert!(theirs.stow("both", "theirs", Some("password")).is_ok());
+        assert!(theirs.stow("only", "theirs", Some("password")).is_ok());
+
+        let report = ours
+            .merge(&theirs, Some("password"), depot::Conflict::Skip)
+            .unwrap();
+        assert_eq!(report.added, vec!["only"]);
+        assert!(report.updated.is_empty());
+        assert_eq!(report.skipped, vec!["both"]);
+        assert_eq!(ours.fetch("both", None).unwrap(), "ours");
+        assert_eq!(ours.fetch("only", Some("password")).unwrap(), "theirs");
+    }
+
+    #[test]
+    fn test_merge_overwrite() {
+        let (ours_db, theirs_db) = (TempDb::new(), TempDb::new());
+        let ours = depot::Depot::new(ours_db.path()).unwrap();
+        let theirs = depot::Depot::new(theirs_db.path()).unwrap();
+        assert!(ours.stow("both", "ours", None).is_ok());
+        assert!(ours.stow("only", "ours", Some("password")).is_ok());
+        assert!(theirs.stow("both", "theirs", Some("password")).is_ok());
+        assert!(theirs.stow("only", "theirs", Some("password")).is_ok());
+
+        let report = ours
+            .merge(&theirs, Some("password"), depot::Conflict::Overwrite)
+            .unwrap();
+        assert!(report.added.is_empty());
+        assert_eq!(report.updated, vec!["both", "only"]);
+        assert_eq!(ours.fetch("both", Some("password")).unwrap(), "theirs");
+
+        // Ours were just overwritten, so theirs are no newer.
+        let report = ours
+            .merge(&theirs, Some("password"), depot::Conflict::KeepNewer)
+            .unwrap();
+        assert_eq!(report.skipped, vec!["both", "only"]);
+    }
+
+    #[test]
+    fn test_expiring() {
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        assert!(storage
+            .stow_expiring("expired1", "testing123", None, Some(now - 60))
+            .is_ok());
+        assert!(storage
+            .stow_expiring("expired2", "testing456", Some("password"), Some(now + 3600))
+            .is_ok());
+        assert!(matches!(
+            storage.fetch("expired1", None),
+            Err(depot::Error::Expired(k)) if k == "expired1"
+        ));
+        assert_eq!(
+            storage.fetch("expired2", Some("password")).unwrap(),
+            "testing456"
+        );
+
+        assert!(storage.prune_expired().unwrap() >= 1);
+        assert!(matches!(
+            storage.fetch("expired1", None),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_prune_on_open() {
+        let db = TempDb::new();
+        let path = db.path();
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage
+            .stow_expiring("prune1", "testing123", None, Some(0))
+            .is_ok());
+        assert!(storage.stow("prune2", "testing456", None).is_ok());
+        drop(storage);
+
+        let storage = depot::Depot::with_options(
+            path,
+            depot::Options {
+                prune_on_open: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(storage.keys().unwrap(), vec!["prune2"]);
+        assert_eq!(storage.prune_expired().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_track_access() {
+        let db = TempDb::new();
+        let path = db.path();
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage.stow("access1", "testing123", None).is_ok());
+        assert!(storage.fetch("access1", None).is_ok());
+        let meta = storage.metadata("access1").unwrap();
+        assert_eq!(meta.accessed, None);
+        assert_eq!(meta.access_count, 0);
+        assert!(meta.modified.is_some());
+        assert_eq!(meta.expires, None);
+        drop(storage);
+
+        let storage = depot::Depot::with_options(
+            path,
+            depot::Options {
+                track_access: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(storage.fetch("access1", None).is_ok());
+        assert!(storage.fetch("access1", Some("password")).is_ok());
+        assert!(storage.fetch_many(&["access1"], None).is_ok());
+        let meta = storage.metadata("access1").unwrap();
+        assert!(meta.accessed.is_some());
+        assert_eq!(meta.access_count, 3);
+
+        assert!(matches!(
+            storage.metadata("access2"),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_history() {
+        let db = TempDb::new();
+        let path = db.path();
+        let storage = depot::Depot::with_options(
+            path,
+            depot::Options {
+                history_limit: 2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(storage.stow("history1", "one", Some("password")).is_ok());
+        assert!(storage.history("history1").unwrap().is_empty());
+        assert!(storage.stow("history1", "two", None).is_ok());
+        assert!(storage.stow("history1", "three", None).is_ok());
+        assert!(storage.stow("history1", "four", None).is_ok());
+
+        let history = storage.history("history1").unwrap();
+        assert_eq!(
+            history.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert!(!history[0].encrypted);
+        assert!(storage.clear().is_ok());
+        assert!(storage.history("history1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restore_after_change_password() {
+        let db = TempDb::new();
+        let storage = depot::Depot::with_options(
+            db.path(),
+            depot::Options {
+                history_limit: 2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(storage.stow("history1", "one", Some("password")).is_ok());
+        assert!(storage.stow("history1", "two", Some("password")).is_ok());
+
+        assert_eq!(storage.change_password("password", "changed").unwrap(), 1);
+        assert!(storage.restore("history1", 1).is_ok());
+        assert_eq!(storage.fetch("history1", Some("changed")).unwrap(), "one");
+        assert!(!storage.has_custom_password("history1").unwrap());
+
+        assert!(storage.rotate_salt("changed").is_ok());
+        assert!(storage.restore("history1", 2).is_ok());
+        assert_eq!(storage.fetch("history1", Some("changed")).unwrap(), "two");
+        assert_eq!(storage.change_password("changed", "again").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_restore() {
+        let db = TempDb::new();
+        let storage = depot::Depot::with_options(
+            db.path(),
+            depot::Options {
+                history_limit: 2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        for val in ["one", "two", "three", "four"] {
+            assert!(storage.stow("history1", val, None).is_ok());
+        }
+
+        assert!(matches!(
+            storage.restore("history1", 1),
+            Err(depot::Error::NotFound(_))
+        ));
+        assert!(storage.restore("history1", 2).is_ok());
+        assert_eq!(storage.fetch("history1", None).unwrap(), "two");
+        assert_eq!(
+            storage
+                .history("history1")
+                .unwrap()
+                .iter()
+                .map(|v| v.version)
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        assert!(storage.restore("history1", 4).is_ok());
+        assert_eq!(storage.fetch("history1", None).unwrap(), "four");
+    }
+
+    #[test]
+    fn test_soft_delete() {
+        let db = TempDb::new();
+        let path = db.path();
+        let storage = depot::Depot::with_options(
+            path,
+            depot::Options {
+                soft_delete: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(storage
+            .stow("trash1", "testing123", Some("password"))
+            .is_ok());
+        assert!(storage.stow("trash2", "testing456", None).is_ok());
+        assert!(storage.drop("trash1").unwrap());
+        assert!(!storage.drop("trash1").unwrap());
+        assert!(matches!(
+            storage.fetch("trash1", Some("password")),
+            Err(depot::Error::NotFound(_))
+        ));
+        assert_eq!(storage.keys().unwrap(), vec!["trash2"]);
+        assert_eq!(storage.stats().unwrap().total, 1);
+    }
+
+    #[test]
+    fn test_undelete() {
+        let db = TempDb::new();
+        let storage = depot::Depot::with_options(
+            db.path(),
+            depot::Options {
+                soft_delete: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(storage
+            .stow("trash1", "testing123", Some("password"))
+            .is_ok());
+        assert!(storage.drop("trash1").unwrap());
+
+        assert!(storage.undelete("trash1").is_ok());
+        assert!(matches!(
+            storage.undelete("trash1"),
+            Err(depot::Error::NotFound(_))
+        ));
+        assert_eq!(
+            storage.fetch("trash1", Some("password")).unwrap(),
+            "testing123"
+        );
+    }
+
+    #[test]
+    fn test_empty_trash() {
+        let db = TempDb::new();
+        let storage = depot::Depot::with_options(
+            db.path(),
+            depot::Options {
+                soft_delete: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(storage.stow("trash1", "testing123", None).is_ok());
+        assert!(storage.stow("trash2", "testing456", None).is_ok());
+
+        assert!(storage.drop("trash1").unwrap());
+        assert!(storage.drop("trash2").unwrap());
+        assert!(storage.copy("trash2", "trash1", None).is_err());
+        assert!(storage.stow("trash2", "testing789", None).is_ok());
+        assert_eq!(storage.empty_trash().unwrap(), 1);
+        assert!(matches!(
+            storage.undelete("trash1"),
+            Err(depot::Error::NotFound(_))
+        ));
+        assert_eq!(storage.fetch("trash2", None).unwrap(), "testing789");
+    }
+
+    #[test]
+    fn test_hint() {
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage
+            .stow_with_hint("hint1", "testing123", Some("custom"), "the usual")
+            .is_ok());
+        assert_eq!(storage.hint("hint1").unwrap().as_deref(), Some("the usual"));
+        assert_eq!(
+            storage.fetch("hint1", Some("custom")).unwrap(),
+            "testing123"
+        );
+
+        assert!(storage.stow("hint1", "testing456", None).is_ok());
+        assert_eq!(storage.hint("hint1").unwrap(), None);
+        assert!(matches!(
+            storage.hint("hint2"),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn test_import_csv() {
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        let csv = "name,url,username,password\n\
+            csv1,https://example.com,me,testing123\n\
+            \"csv2\",,,\"comma, \"\"quote\"\"\"\n";
+        let mapping = depot::CsvMapping::default();
+        assert_eq!(
+            storage
+                .import_csv(csv.as_bytes(), &mapping, "password")
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            storage.fetch("csv1", Some("password")).unwrap(),
+            "testing123"
+        );
+        assert_eq!(
+            storage.fetch("csv2", Some("password")).unwrap(),
+            "comma, \"quote\""
+        );
+
+        let mapping = depot::CsvMapping {
+            key: String::from("title"),
+            ..Default::default()
+        };
+        assert!(storage
+            .import_csv(csv.as_bytes(), &mapping, "password")
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn test_export_csv() {
+        let db = TempDb::new();
+        let path = db.path();
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage.stow("export1", "plain", None).is_ok());
+        assert!(storage
+            .stow("export2", "a, \"quoted\"\nvalue", Some("password"))
+            .is_ok());
+
+        let mut out = Vec::new();
+        assert_eq!(storage.export_csv(&mut out, Some("password")).unwrap(), 2);
+        assert_eq!(
+            String::from_utf8(out.clone()).unwrap(),
+            "name,password\nexport1,plain\nexport2,\"a, \"\"quoted\"\"\nvalue\"\n"
+        );
+
+        assert!(storage.clear().is_ok());
+        let mapping = depot::CsvMapping::default();
+        assert_eq!(
+            storage
+                .import_csv(out.as_slice(), &mapping, "password")
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            storage.fetch("export2", Some("password")).unwrap(),
+            "a, \"quoted\"\nvalue"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn test_export_csv_password() {
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow("export1", "plain", None).is_ok());
+        assert!(storage.stow("export2", "secret", Some("password")).is_ok());
+
+        let mut out = Vec::new();
+        assert!(matches!(
+            storage.export_csv(&mut out, None),
+            Err(depot::Error::NeedPassword)
+        ));
+        assert!(matches!(
+            storage.export_csv(&mut out, Some("wrong")),
+            Err(depot::Error::BadPassword)
+        ));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_export_encrypted() {
+        let (src_db, dst_db) = (TempDb::new(), TempDb::new());
+        let src = depot::Depot::new(src_db.path()).unwrap();
+        assert!(src.stow("export1", "plain", None).is_ok());
+        assert!(src.stow("export2", "supersecret", Some("password")).is_ok());
+        assert!(src
+            .stow_with_hint("export3", "testing123", Some("custom"), "the usual")
+            .is_ok());
+        assert!(src.tag("export1", &["work", "mail"]).is_ok());
+
+        let data = src.export_encrypted().unwrap();
+        assert!(!data
+            .windows("supersecret".len())
+            .any(|w| w == b"supersecret"));
+
+        let dst = depot::Depot::with_options(
+            dst_db.path(),
+            depot::Options {
+                url_safe_base64: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(dst.import_encrypted(&data[..data.len() - 1]).is_err());
+        assert_eq!(dst.import_encrypted(&data).unwrap(), 3);
+        assert_eq!(dst.fetch("export1", None).unwrap(), "plain");
+        assert_eq!(
+            dst.fetch("export2", Some("password")).unwrap(),
+            "supersecret"
+        );
+        assert_eq!(dst.fetch("export3", Some("custom")).unwrap(), "testing123");
+        assert_eq!(dst.hint("export3").unwrap().as_deref(), Some("the usual"));
+        assert_eq!(dst.tags("export1").unwrap(), vec!["mail", "work"]);
+        assert!(dst.verify_password("password").unwrap());
+        assert!(!dst.verify_password("wrong").unwrap());
+        assert!(dst.has_custom_password("export3").unwrap());
+    }
+
+    #[test]
+    fn test_import_encrypted_v1() {
+        let (src_db, dst_db) = (TempDb::new(), TempDb::new());
+        let src = depot::Depot::new(src_db.path()).unwrap();
+        assert!(src.stow("export1", "plain", None).is_ok());
+
+        // A version 1 export is the same as version 2 without the tags, which
+        // for an untagged entry is the last byte.
+        let mut data = src.export_encrypted().unwrap();
+        assert_eq!(data.pop(), Some(0));
+        data[8] = 1;
+
+        let dst = depot::Depot::new(dst_db.path()).unwrap();
+        assert!(dst.stow("export1", "old", None).is_ok());
+        assert!(dst.tag("export1", &["work"]).is_ok());
+        assert_eq!(dst.import_encrypted(&data).unwrap(), 1);
+        assert_eq!(dst.fetch("export1", None).unwrap(), "plain");
+        assert_eq!(dst.tags("export1").unwrap(), vec!["work"]);
+    }
+
+    #[test]
+    fn test_import_encrypted_again() {
+        let (src_db, dst_db) = (TempDb::new(), TempDb::new());
+        let src = depot::Depot::new(src_db.path()).unwrap();
+        assert!(src.stow("export2", "supersecret", Some("password")).is_ok());
+        let data = src.export_encrypted().unwrap();
+
+        let dst = depot::Depot::new(dst_db.path()).unwrap();
+        assert_eq!(dst.import_encrypted(&data).unwrap(), 1);
+        drop(dst);
+
+        let dst = depot::Depot::new(dst_db.path()).unwrap();
+        assert_eq!(
+            dst.fetch("export2", Some("password")).unwrap(),
+            "supersecret"
+        );
+        assert_eq!(dst.import_encrypted(&data).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_import_encrypted_other_salt() {
+        let (src_db, dst_db) = (TempDb::new(), TempDb::new());
+        let src = depot::Depot::new(src_db.path()).unwrap();
+        let dst = depot::Depot::new(dst_db.path()).unwrap();
+        assert!(src.stow("export2", "supersecret", Some("password")).is_ok());
+        assert_eq!(
+            dst.import_encrypted(&src.export_encrypted().unwrap())
+                .unwrap(),
+            1
+        );
+
+        assert!(src.clear().is_ok());
+        assert!(src.rotate_salt("password").is_ok());
+        assert!(src.stow("export4", "testing456", Some("password")).is_ok());
+        assert!(dst
+            .import_encrypted(&src.export_encrypted().unwrap())
+            .is_err());
+        assert!(matches!(
+            dst.fetch("export4", Some("password")),
+            Err(depot::Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_busy_retry() {
+        let db = TempDb::new();
+        let path = db.path();
+        let opts = |busy_retries| depot::Options {
+            busy_timeout: std::time::Duration::from_millis(1),
+            busy_retries,
+            busy_backoff: std::time::Duration::from_millis(20),
+            ..Default::default()
+        };
+        let patient = depot::Depot::with_options(path, opts(5)).unwrap();
+        let impatient = depot::Depot::with_options(path, opts(0)).unwrap();
+
+        let lock = rusqlite::Connection::open(path).unwrap();
+        lock.execute_batch("begin exclusive").unwrap();
+        assert!(matches!(
+            impatient.stow("busy1", "testing123", None),
+            Err(depot::Error::SqlErr(_))
+        ));
+
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            lock.execute_batch("commit").unwrap();
+        });
+        assert!(patient.stow("busy1", "testing123", None).is_ok());
+        holder.join().unwrap();
+        assert_eq!(impatient.fetch("busy1", None).unwrap(), "testing123");
+    }
+
+    #[test]
+    fn test_busy_retry_undelete() {
+        let db = TempDb::new();
+        let opts = depot::Options {
+            busy_timeout: std::time::Duration::from_millis(1),
+            busy_retries: 5,
+            busy_backoff: std::time::Duration::from_millis(20),
+            soft_delete: true,
+            ..Default::default()
+        };
+        let patient = depot::Depot::with_options(db.path(), opts).unwrap();
+        assert!(patient.stow("busy1", "testing123", None).is_ok());
+        assert!(patient.drop("busy1").unwrap());
+
+        let lock = rusqlite::Connection::open(db.path()).unwrap();
+        lock.execute_batch("begin exclusive").unwrap();
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            lock.execute_batch("commit").unwrap();
+        });
+        assert!(patient.undelete("busy1").is_ok());
+        holder.join().unwrap();
+        assert_eq!(patient.keys().unwrap(), vec!["busy1"]);
+    }
+
+    #[test]
+    fn test_scoped() {
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        let mail = storage.scoped("mail");
+        let chat = storage.scoped("chat");
+
+        assert!(mail.stow("scoped", "testing123", None).is_ok());
+        assert!(chat.stow("scoped", "testing456", Some("password")).is_ok());
+        assert_eq!(mail.fetch("scoped", None).unwrap(), "testing123");
+        assert_eq!(
+            chat.fetch("scoped", Some("password")).unwrap(),
+            "testing456"
+        );
+        assert_eq!(storage.fetch("mail/scoped", None).unwrap(), "testing123");
+        assert_eq!(mail.keys().unwrap(), vec!["scoped"]);
+
+        assert!(mail.drop("scoped").is_ok());
+        assert!(chat.drop("scoped").is_ok());
+        assert!(mail.keys().unwrap().is_empty());
+
+        assert!(matches!(
+            mail.stow("", "testing123", None),
+            Err(depot::Error::InvalidKey(_))
+        ));
+        assert!(matches!(
+            mail.stow("  ", "testing123", None),
+            Err(depot::Error::InvalidKey(_))
+        ));
+        assert!(storage.keys().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_value() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Account {
+            user: String,
+            port: u16,
+        }
+
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        let account = Account {
+            user: String::from("depot"),
+            port: 993,
+        };
+        assert!(storage
+            .stow_value("value", &account, Some("password"))
+            .is_ok());
+        assert_eq!(
+            storage
+                .fetch_value::<Account>("value", Some("password"))
+                .unwrap(),
+            account
+        );
+        assert!(matches!(
+            storage.fetch_value::<u16>("value", Some("password")),
+            Err(depot::Error::JsonErr(_))
+        ));
+    }
+
+    #[test]
+    fn test_kdf_iterations() {
+        let db = TempDb::new();
+        let path = db.path();
+
+        let opts = depot::Options {
+            kdf_iterations: 1000,
+            ..Default::default()
+        };
+        let storage = depot::Depot::with_options(path, opts.clone()).unwrap();
+        assert!(storage.stow("kdf", "testing123", Some("password")).is_ok());
+        drop(storage);
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage.verify_password("password").unwrap());
+        assert_eq!(
+            storage.fetch("kdf", Some("password")).unwrap(),
+            "testing123"
+        );
+
+        let opts = depot::Options {
+            kdf_iterations: 0,
+            ..opts
+        };
+        assert!(depot::Depot::with_options(path, opts).is_err());
+    }
+
+    #[test]
+    fn test_fetch_without_upgrade() {
+        let db = TempDb::new();
+        let path = db.path();
+        let kdf = |key: &str| -> i64 {
+            rusqlite::Connection::open(path)
+                .unwrap()
+                .query_row("select kdf from storage where key = ?", (key,), |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+
+        let legacy = depot::Options {
+            kdf_hash: depot::KdfHash::Sha1,
+            ..Default::default()
+        };
+        let storage = depot::Depot::with_options(path, legacy).unwrap();
+        assert!(storage
+            .stow("upgrade1", "testing123", Some("password"))
+            .is_ok());
+        assert!(storage
+            .stow("upgrade2", "testing123", Some("password"))
+            .is_ok());
+        drop(storage);
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert_eq!(
+            storage.fetch("upgrade1", Some("password")).unwrap(),
+            "testing123"
+        );
+        assert_eq!(kdf("upgrade1"), 0);
+    }
+
+    #[test]
+    fn test_upgrade_on_fetch() {
+        let db = TempDb::new();
+        let path = db.path();
+        let kdf = |key: &str| -> i64 {
+            rusqlite::Connection::open(path)
+                .unwrap()
+                .query_row("select kdf from storage where key = ?", (key,), |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+
+        let legacy = depot::Options {
+            kdf_hash: depot::KdfHash::Sha1,
+            ..Default::default()
+        };
+        let storage = depot::Depot::with_options(path, legacy).unwrap();
+        assert!(storage
+            .stow("upgrade1", "testing123", Some("password"))
+            .is_ok());
+        assert!(storage
+            .stow("upgrade2", "testing123", Some("password"))
+            .is_ok());
+        drop(storage);
+
+        let upgrading = depot::Options {
+            upgrade_on_fetch: true,
+            ..Default::default()
+        };
+        let storage = depot::Depot::with_options(path, upgrading).unwrap();
+        assert!(storage.fetch("upgrade1", Some("wrong")).is_err());
+        assert_eq!(kdf("upgrade1"), 0);
+        assert_eq!(
+            storage.fetch("upgrade1", Some("password")).unwrap(),
+            "testing123"
+        );
+        assert_eq!(kdf("upgrade1"), 1);
+        assert_eq!(kdf("upgrade2"), 0);
+        assert_eq!(
+            storage.fetch("upgrade1", Some("password")).unwrap(),
+            "testing123"
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let db = TempDb::new();
+        let path = db.path();
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage
+            .stow("check1", "testing123", Some("password"))
+            .is_ok());
+        assert!(storage
+            .stow("check2", "testing123", Some("password"))
+            .is_ok());
+        assert!(storage.stow("check3", "testing123", Some("custom")).is_ok());
+        assert!(storage.stow("check4", "testing123", None).is_ok());
+        assert_eq!(storage.check("password").unwrap(), vec!["check3"]);
+
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute(
+            "update storage set val = 'AAAA' || substr(val, 5) where key = 'check2'",
+            (),
+        )
+        .unwrap();
+        assert_eq!(storage.check("password").unwrap(), vec!["check2", "check3"]);
+    }
+
+    #[test]
+    fn test_url_safe_base64() {
+        let db = TempDb::new();
+        let path = db.path();
+
+        let opts = depot::Options {
+            url_safe_base64: true,
+            ..Default::default()
+        };
+        let storage = depot::Depot::with_options(path, opts).unwrap();
+        assert!(storage
+            .stow("urlsafe1", "testing123", Some("password"))
+            .is_ok());
+        drop(storage);
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage
+            .stow("urlsafe2", "testing123", Some("password"))
+            .is_ok());
+        for key in ["urlsafe1", "urlsafe2"] {
+            assert_eq!(storage.fetch(key, Some("password")).unwrap(), "testing123");
+        }
+
+        let conn = rusqlite::Connection::open(path).unwrap();
+        let vals: Vec<String> = conn
+            .prepare("select val from storage")
+            .unwrap()
+            .query_map((), |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert!(vals.iter().all(|v| !v.contains(['+', '/'])));
+    }
+
+    #[test]
+    fn test_drop() {
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow("drop", "testing123", None).is_ok());
+        assert!(storage.drop("drop").unwrap());
+        assert!(!storage.drop("drop").unwrap());
+    }
+
+    #[test]
+    fn test_fetch_many() {
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        assert!(storage.stow("many1", "testing123", None).is_ok());
+        assert!(storage
+            .stow("many2", "testing456", Some("password"))
+            .is_ok());
+        assert!(storage.stow("many3", "testing789", Some("custom")).is_ok());
+
+        let vals = storage
+            .fetch_many(&["many2", "many1", "many3", "many4"], Some("password"))
+            .unwrap();
+        let keys: Vec<&str> = vals.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["many2", "many1", "many3", "many4"]);
+        assert_eq!(vals[0].1.as_ref().unwrap(), "testing456");
+        assert_eq!(vals[1].1.as_ref().unwrap(), "testing123");
+        assert!(matches!(vals[2].1, Err(depot::Error::BadPassword)));
+        assert!(matches!(vals[3].1, Err(depot::Error::NotFound(_))));
+        assert!(storage.fetch_many(&[], None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_open() {
+        let db = TempDb::new();
+        let path = db.path();
+
+        assert!(matches!(
+            depot::Depot::open(path),
+            Err(depot::Error::NoDepot(p)) if p == path
+        ));
+        assert!(!std::path::Path::new(path).exists());
+
+        rusqlite::Connection::open(path).unwrap();
+        assert!(matches!(
+            depot::Depot::open(path),
+            Err(depot::Error::NoDepot(_))
+        ));
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage.stow("open", "testing123", None).is_ok());
+        drop(storage);
+        let storage = depot::Depot::open(path).unwrap();
+        assert_eq!(storage.fetch("open", None).unwrap(), "testing123");
+    }
+
+    #[test]
+    fn test_rotate_salt() {
+        let db = TempDb::new();
+        let path = db.path();
+        let salt = || -> Vec<u8> {
+            rusqlite::Connection::open(path)
+                .unwrap()
+                .query_row("select data from salt", (), |row| row.get(0))
+                .unwrap()
+        };
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage
+            .stow("rotate1", "testing123", Some("password"))
+            .is_ok());
+        assert!(storage.stow("rotate2", "testing456", None).is_ok());
+        let old = salt();
+
+        assert!(matches!(
+            storage.rotate_salt("wrong"),
+            Err(depot::Error::BadPassword)
+        ));
+        assert!(storage.rotate_salt("password").is_ok());
+        assert_ne!(salt(), old);
+        assert_eq!(
+            storage.fetch("rotate1", Some("password")).unwrap(),
+            "testing123"
+        );
+        assert_eq!(storage.fetch("rotate2", None).unwrap(), "testing456");
+        drop(storage);
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage.verify_password("password").unwrap());
+        assert_eq!(
+            storage.fetch("rotate1", Some("password")).unwrap(),
+            "testing123"
+        );
+    }
+
+    #[test]
+    fn test_rotate_salt_custom() {
+        let db = TempDb::new();
+        let path = db.path();
+        let salt = || -> Vec<u8> {
+            rusqlite::Connection::open(path)
+                .unwrap()
+                .query_row("select data from salt", (), |row| row.get(0))
+                .unwrap()
+        };
+
+        let storage = depot::Depot::new(path).unwrap();
+        assert!(storage
+            .stow("rotate1", "testing123", Some("password"))
+            .is_ok());
+        assert!(storage
+            .stow("rotate3", "testing789", Some("custom"))
+            .is_ok());
+        let old = salt();
+        assert!(storage.rotate_salt("password").is_err());
+        assert_eq!(salt(), old);
+        assert_eq!(
+            storage.fetch("rotate3", Some("custom")).unwrap(),
+            "testing789"
+        );
+    }
+
     #[test]
     fn test_bad_key() {
-        let storage = depot::Depot::new(DB_PATH).unwrap();
-        assert!(storage.fetch("badkey", None).is_err());
+        let db = TempDb::new();
+        let storage = depot::Depot::new(db.path()).unwrap();
+        match storage.fetch("badkey", None) {
+            Err(depot::Error::NotFound(k)) => assert_eq!(k, "badkey"),
+            _ => panic!("expected NotFound"),
+        }
     }
 }